@@ -0,0 +1,55 @@
+use std::time::Instant;
+
+use silica::parsing::lex::lex;
+use silica::parsing::symbol::SymbolTable;
+
+const SAMPLE : &str = r##"
+/// Adds two numbers.
+fun add_numbers x y = x + y ;
+
+/* block /* nested */ comment */
+spec map<a, b> = (a -> b) -> List<a> -> List<b> ;
+
+data Shape<a> = Circle(Decimal) | Rect(Decimal, Decimal) | Named(String, a) ;
+
+fun area shape = match shape {
+    Circle(r) => 3.14159 * r * r,
+    Rect(w, h) => w * h,
+    Named(_, s) => area(s),
+} ;
+
+let mask = 0xFF_FF ;
+let big = 1_000_000 ;
+let sci = 6.02e23 ;
+let greeting = "hello, ${name}! \t\u{1F600}" ;
+let raw = r#"{"json": true}"# ;
+let λ = 'λ' ;
+"##;
+
+fn main() {
+    let target = 4 * 1024 * 1024;
+    let mut source = String::with_capacity(target + SAMPLE.len());
+    while source.len() < target {
+        source.push_str(SAMPLE);
+    }
+
+    let mut symbols = SymbolTable::new();
+
+    let runs = 5;
+    let mut best = f64::MAX;
+    let mut tokens = 0;
+
+    for _ in 0..runs {
+        let start = Instant::now();
+        let r = lex(&source, &mut symbols).expect("benchmark input should lex");
+        let elapsed = start.elapsed().as_secs_f64();
+
+        tokens = r.len();
+        if elapsed < best {
+            best = elapsed;
+        }
+    }
+
+    let mb = source.len() as f64 / (1024.0 * 1024.0);
+    println!("lexed {:.2} MB ({} tokens) in {:.3}s: {:.2} MB/s", mb, tokens, best, mb / best);
+}
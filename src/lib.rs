@@ -0,0 +1,3 @@
+
+pub mod parsing;
+pub mod loader;
@@ -0,0 +1,569 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::parsing::ast::{Assoc, Ast, Expr, ExprKind, Ident, Pattern, PatternKind, Type, TypeKind};
+use crate::parsing::desugar::desugar;
+use crate::parsing::lex::{lex, LexError};
+use crate::parsing::lexeme::Span;
+use crate::parsing::parser::{parse_module, parse_module_with_fixities, ParseError};
+use crate::parsing::prelude;
+use crate::parsing::symbol::{Symbol, SymbolTable};
+
+pub struct Module {
+    // Module 'a.b' is loaded from 'a/b.si' under the root.
+    pub name : String,
+    pub file : PathBuf,
+    pub decls : Vec<Ast>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum LoadError {
+    Io { file : PathBuf, message : String },
+    Lex { file : PathBuf, error : LexError },
+    Parse { file : PathBuf, error : ParseError },
+    // The module declaration in the file doesn't match the path it was imported by.
+    WrongName { file : PathBuf, expected : String, found : String },
+    // Each module imports the next one, and the last imports the first.
+    Cycle { modules : Vec<String> },
+    // A qualified name whose qualifier doesn't match any import.
+    UnknownModule { file : PathBuf, qualifier : String, span : Span },
+    // Two imported modules have the same qualifier, as in 'import a.list; import b.list;'.
+    // The span is the second import's.
+    AmbiguousQualifier { file : PathBuf, qualifier : String, modules : Vec<String>, span : Span },
+    NotExported { file : PathBuf, module : String, name : String, span : Span },
+    // Two imported modules give the same operator different public fixities.  The span 
+    // is the second import's.
+    ConflictingFixity { file : PathBuf, op : String, span : Span },
+}
+
+/// Loads the module with the given name and everything it imports, returning them in
+/// an order where every module comes after the ones it imports.  The prelude always 
+/// comes first, and the modules come back desugared.
+pub fn load(root : &Path, name : &str, symbols : &mut SymbolTable) -> Result<Vec<Module>, LoadError> {
+    // The prelude is built in rather than read from the root, so a file called 
+    // 'prelude.si' is never loaded.
+    let decls = prelude::prelude(symbols);
+    let prelude = Module { name : prelude::NAME.to_string(), file : PathBuf::from("prelude.si"), decls };
+
+    let mut loader = Loader { root, symbols, modules : vec![prelude], loading : vec![] };
+    loader.visit(name)?;
+    Ok(loader.modules)
+}
+
+pub fn module_file(root : &Path, name : &str) -> PathBuf {
+    let mut file = root.to_path_buf();
+    file.extend(name.split('.'));
+    file.set_extension("si");
+    file
+}
+
+struct Loader<'a> {
+    root : &'a Path,
+    symbols : &'a mut SymbolTable,
+    modules : Vec<Module>,
+    // The chain of imports currently being loaded, for finding cycles.
+    loading : Vec<String>,
+}
+
+impl<'a> Loader<'a> {
+    fn visit(&mut self, name : &str) -> Result<(), LoadError> {
+        if self.modules.iter().any(|m| m.name == name) {
+            return Ok(());
+        }
+
+        if let Some(i) = self.loading.iter().position(|m| m == name) {
+            return Err(LoadError::Cycle { modules : self.loading[i..].to_vec() });
+        }
+
+        let file = module_file(self.root, name);
+        let src = fs::read_to_string(&file).map_err(|e| LoadError::Io { file : file.clone(), message : e.to_string() })?;
+        let tokens = lex(&src, self.symbols).map_err(|error| LoadError::Lex { file : file.clone(), error })?;
+        let mut decls = parse_module(tokens.clone(), self.symbols).map_err(|error| LoadError::Parse { file : file.clone(), error })?;
+
+        for decl in &decls {
+            if let Ast::Module { path, .. } = decl {
+                let found = self.path_name(path);
+                if found != name {
+                    return Err(LoadError::WrongName { file, expected : name.to_string(), found });
+                }
+            }
+        }
+
+        self.loading.push(name.to_string());
+        for decl in &decls {
+            if let Ast::Import { path, .. } = decl {
+                let import = self.path_name(path);
+                self.visit(&import)?;
+            }
+        }
+        self.loading.pop();
+
+        // Imported operators need their fixities to parse right, and those are only 
+        // known once the imports are loaded.
+        let fixities = self.imported_fixities(&file, &decls)?;
+        if !fixities.is_empty() {
+            decls = parse_module_with_fixities(tokens, self.symbols, &fixities).map_err(|error| LoadError::Parse { file : file.clone(), error })?;
+        }
+
+        self.check_imports(&file, &decls)?;
+
+        // After the checks, so that they only see what was written.
+        decls.iter_mut().for_each(|d| desugar(d, self.symbols));
+
+        self.modules.push(Module { name : name.to_string(), file, decls });
+        Ok(())
+    }
+
+    // Everything imported by name or used qualified has to be public in its module.
+    // The imported modules are already loaded.
+    fn check_imports(&self, file : &Path, decls : &[Ast]) -> Result<(), LoadError> {
+        // Every module imports the prelude.  It comes first so that 'Prelude.Cons' is 
+        // always the prelude's even if another import has the same qualifier.
+        let prelude = &self.modules.iter().find(|m| m.name == prelude::NAME).unwrap().decls;
+        let mut imports = vec![(qualifier(prelude::NAME), prelude::NAME.to_string(), exports(prelude))];
+        for decl in decls {
+            if let Ast::Import { path, names, span } = decl {
+                let module = self.path_name(path);
+                let exports = exports(&self.modules.iter().find(|m| m.name == module).unwrap().decls);
+                let qualifier = qualifier(self.symbols.resolve(path.last().unwrap().symbol));
+
+                // The prelude's qualifier always means the prelude, so it never clashes.
+                if module != prelude::NAME {
+                    if let Some((_, other, _)) = imports[1..].iter().find(|(q, m, _)| *q == qualifier && *m != module && m != prelude::NAME) {
+                        return Err(LoadError::AmbiguousQualifier { file : file.to_path_buf(), qualifier, modules : vec![other.clone(), module], span : *span });
+                    }
+                }
+
+                for name in names {
+                    if !exports.contains(&name.symbol) {
+                        return Err(self.not_exported(file, &module, name.symbol, name.span));
+                    }
+                }
+
+                imports.push((qualifier, module, exports));
+            }
+        }
+
+        let mut used = vec![];
+        decls.iter().for_each(|d| names_in_decl(d, &mut used));
+
+        for (symbol, span) in used {
+            let (qualifier, name) = match self.symbols.resolve(symbol).split_once('.') {
+                Some((qualifier, name)) => (qualifier.to_string(), name.to_string()),
+                None => continue,
+            };
+
+            let (module, exports) = match imports.iter().find(|(q, _, _)| *q == qualifier) {
+                Some((_, module, exports)) => (module, exports),
+                None => return Err(LoadError::UnknownModule { file : file.to_path_buf(), qualifier, span }),
+            };
+
+            if !exports.iter().any(|e| self.symbols.resolve(*e) == name) {
+                return Err(LoadError::NotExported { file : file.to_path_buf(), module : module.clone(), name, span });
+            }
+        }
+
+        Ok(())
+    }
+
+    // The public fixity declarations of every module that decls imports.  The imported 
+    // modules are already loaded.
+    fn imported_fixities(&self, file : &Path, decls : &[Ast]) -> Result<Vec<(Symbol, Assoc, u8)>, LoadError> {
+        let mut fixities : Vec<(Symbol, Assoc, u8)> = vec![];
+        for decl in decls {
+            if let Ast::Import { path, span, .. } = decl {
+                let module = self.path_name(path);
+                let imported = &self.modules.iter().find(|m| m.name == module).unwrap().decls;
+
+                for d in imported {
+                    if let Ast::Fixity { assoc, precedence, op, public : true, .. } = d {
+                        match fixities.iter().find(|(o, _, _)| *o == op.symbol) {
+                            Some(&(_, a, p)) if a != *assoc || p != *precedence => 
+                                return Err(LoadError::ConflictingFixity { file : file.to_path_buf(), op : self.symbols.resolve(op.symbol).to_string(), span : *span }),
+                            Some(_) => { },
+                            None => fixities.push((op.symbol, *assoc, *precedence)),
+                        }
+                    }
+                }
+            }
+        }
+        Ok(fixities)
+    }
+
+    fn not_exported(&self, file : &Path, module : &str, name : Symbol, span : Span) -> LoadError {
+        LoadError::NotExported { file : file.to_path_buf(), module : module.to_string(), name : self.symbols.resolve(name).to_string(), span }
+    }
+
+    fn path_name(&self, path : &[Ident]) -> String {
+        path.iter().map(|p| self.symbols.resolve(p.symbol)).collect::<Vec<_>>().join(".")
+    }
+}
+
+// Names from a module are qualified by the last part of its path with the first letter 
+// in upper case, so 'import std.list' is used as 'List.map'.
+fn qualifier(last : &str) -> String {
+    let mut chars = last.chars();
+    chars.next().map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+}
+
+// The names a module makes public.  A public data type exports its constructors too.
+fn exports(decls : &[Ast]) -> Vec<Symbol> {
+    let mut exports = vec![];
+    for decl in decls {
+        match decl {
+            Ast::Fun { name, public : true, .. } | Ast::Let { name, public : true, .. } => exports.push(name.symbol),
+            Ast::Data { name, constructors, public : true, .. } => {
+                exports.push(name.symbol);
+                exports.extend(constructors.iter().map(|c| c.name.symbol));
+            },
+            _ => { },
+        }
+    }
+    exports
+}
+
+// Collects every variable, constructor and type name that the declaration refers to.
+fn names_in_decl(decl : &Ast, out : &mut Vec<(Symbol, Span)>) {
+    match decl {
+        Ast::Fun { body, .. } => names_in_expr(body, out),
+        Ast::Let { expr, .. } => names_in_expr(expr, out),
+        Ast::Spec { t, .. } => names_in_type(t, out),
+        Ast::Data { constructors, .. } => constructors.iter().for_each(|c| c.fields.iter().for_each(|t| names_in_type(t, out))),
+        Ast::Fixity { .. } | Ast::Module { .. } | Ast::Import { .. } => { },
+    }
+}
+
+fn names_in_expr(e : &Expr, out : &mut Vec<(Symbol, Span)>) {
+    match &e.kind {
+        ExprKind::Bool(_)
+        | ExprKind::Integer(_)
+        | ExprKind::Decimal(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_) => { },
+        ExprKind::Var(s) => out.push((*s, e.span)),
+        ExprKind::Call { fun, args } => {
+            names_in_expr(fun, out);
+            args.iter().for_each(|a| names_in_expr(a, out));
+        },
+        ExprKind::Binary { lhs, rhs, .. } => {
+            names_in_expr(lhs, out);
+            names_in_expr(rhs, out);
+        },
+        ExprKind::Unary { expr, .. } | ExprKind::Field { expr, .. } => names_in_expr(expr, out),
+        ExprKind::If { cond, then, otherwise } => {
+            names_in_expr(cond, out);
+            names_in_expr(then, out);
+            names_in_expr(otherwise, out);
+        },
+        ExprKind::Tuple(es) | ExprKind::List(es) | ExprKind::Interpolated(es) => es.iter().for_each(|e| names_in_expr(e, out)),
+        ExprKind::Constructor { name, args } => {
+            out.push((*name, e.span));
+            args.iter().for_each(|a| names_in_expr(a, out));
+        },
+        ExprKind::Record { name, fields } => {
+            out.push((*name, e.span));
+            fields.iter().for_each(|f| names_in_expr(&f.expr, out));
+        },
+        ExprKind::Update { expr, fields } => {
+            names_in_expr(expr, out);
+            fields.iter().for_each(|f| names_in_expr(&f.expr, out));
+        },
+        ExprKind::Match { expr, arms } => {
+            names_in_expr(expr, out);
+            for arm in arms {
+                names_in_pattern(&arm.pattern, out);
+                if let Some(guard) = &arm.guard {
+                    names_in_expr(guard, out);
+                }
+                names_in_expr(&arm.expr, out);
+            }
+        },
+        ExprKind::Block { statements, expr } => {
+            statements.iter().for_each(|s| names_in_decl(s, out));
+            names_in_expr(expr, out);
+        },
+        ExprKind::Lambda { body, .. } => names_in_expr(body, out),
+    }
+}
+
+fn names_in_pattern(p : &Pattern, out : &mut Vec<(Symbol, Span)>) {
+    match &p.kind {
+        PatternKind::Wildcard
+        | PatternKind::Var(_)
+        | PatternKind::Bool(_)
+        | PatternKind::Integer(_)
+        | PatternKind::Decimal(_)
+        | PatternKind::String(_)
+        | PatternKind::Char(_) => { },
+        PatternKind::Tuple(ps) | PatternKind::Or(ps) => ps.iter().for_each(|p| names_in_pattern(p, out)),
+        PatternKind::Constructor { name, args } => {
+            out.push((*name, p.span));
+            args.iter().for_each(|p| names_in_pattern(p, out));
+        },
+        PatternKind::As { pattern, .. } => names_in_pattern(pattern, out),
+        PatternKind::Record { name, fields } => {
+            out.push((*name, p.span));
+            fields.iter().for_each(|f| names_in_pattern(&f.pattern, out));
+        },
+        PatternKind::List { items, rest } => {
+            items.iter().for_each(|p| names_in_pattern(p, out));
+            if let Some(rest) = rest {
+                names_in_pattern(rest, out);
+            }
+        },
+    }
+}
+
+fn names_in_type(t : &Type, out : &mut Vec<(Symbol, Span)>) {
+    match &t.kind {
+        TypeKind::Generic(_) => { },
+        TypeKind::Concrete(name) => out.push((*name, t.span)),
+        TypeKind::Arrow { src, dest } => {
+            names_in_type(src, out);
+            names_in_type(dest, out);
+        },
+        TypeKind::Tuple(ts) => ts.iter().for_each(|t| names_in_type(t, out)),
+        TypeKind::Applied { name, args } => {
+            out.push((*name, t.span));
+            args.iter().for_each(|t| names_in_type(t, out));
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Writes the files into a fresh directory for the test.
+    fn root(test : &str, files : &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("silica-loader-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for (name, src) in files {
+            let file = module_file(&root, name);
+            fs::create_dir_all(file.parent().unwrap()).expect("create_dir_all should succeed");
+            fs::write(file, src).expect("write should succeed");
+        }
+
+        root
+    }
+
+    #[test]
+    fn module_file_should_map_path_to_file() {
+        assert_eq!( module_file(Path::new("src"), "a.b.c"), Path::new("src/a/b/c.si") );
+        assert_eq!( module_file(Path::new("src"), "main"), Path::new("src/main.si") );
+    }
+
+    #[test]
+    fn qualifier_should_capitalize() {
+        assert_eq!( qualifier("list"), "List" );
+        assert_eq!( qualifier("List"), "List" );
+        assert_eq!( qualifier("io"), "Io" );
+    }
+
+    #[test]
+    fn load_should_load_imports_first() {
+        let root = root("order", &[ ("main", "module main; import util.List (map); import util.io; let x = List.length (map f xs);")
+                                  , ("util.List", "module util.List; import util.io; pub fun map f xs = xs; pub fun length xs = 0;")
+                                  , ("util.io", "pub data Handle = Stdout | Stderr;")
+                                  ]);
+        let mut symbols = SymbolTable::new();
+
+        let modules = load(&root, "main", &mut symbols).expect("load should succeed");
+
+        assert_eq!( modules.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["prelude", "util.io", "util.List", "main"] );
+        assert_eq!( modules[3].file, root.join("main.si") );
+        assert_eq!( modules[3].decls.len(), 4 );
+    }
+
+    #[test]
+    fn load_should_desugar_bool_operators() {
+        let root = root("bool", &[ ("main", "let a = if x { 1 } else { 2 }; let b = x && y; let c = x || y; let d = !x;") ]);
+        let mut symbols = SymbolTable::new();
+
+        let modules = load(&root, "main", &mut symbols).expect("load should succeed");
+
+        for decl in &modules[1].decls {
+            match decl {
+                Ast::Let { expr : Expr { kind : ExprKind::Match { arms, .. }, .. }, .. } => {
+                    assert_eq!( arms[0].pattern.kind, PatternKind::Bool(true) );
+                    assert_eq!( arms[1].pattern.kind, PatternKind::Bool(false) );
+                },
+                x => panic!("expected match but found {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn load_should_desugar_lists_to_prelude() {
+        let root = root("prelude", &[ ("main", "import prelude (List); data Mine = Cons(Int) | Nil; let xs = [1]; let ys = Prelude.Nil;") ]);
+        let mut symbols = SymbolTable::new();
+
+        let modules = load(&root, "main", &mut symbols).expect("load should succeed");
+
+        assert_eq!( modules.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), vec!["prelude", "main"] );
+
+        let cons = symbols.intern("Prelude.Cons");
+        let nil = symbols.intern("Prelude.Nil");
+        match &modules[1].decls[2] {
+            Ast::Let { expr : Expr { kind : ExprKind::Constructor { name, args }, .. }, .. } => {
+                assert_eq!( *name, cons );
+                assert_eq!( args[0].kind, ExprKind::Integer(1) );
+                assert_eq!( args[1].kind, ExprKind::Constructor { name : nil, args : vec![] } );
+            },
+            x => panic!("expected constructor but found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn load_should_parse_with_imported_fixities() {
+        let table = vec![ ("import lib ((<$>)); let x = a <$> b <$> c;", "(a <$> (b <$> c))")
+                        , ("import lib; let x = a <$> b + c;", "(a <$> (b + c))")
+                        // Only public fixities are imported.
+                        , ("import lib; let x = a <|> b <|> c;", "((a <|> b) <|> c)")
+                        , ("import lib; infixl 1 <$>; let x = a <$> b <$> c;", "((a <$> b) <$> c)")
+                        ];
+
+        for (main, expected) in table {
+            let root = root("fixity", &[ ("main", main)
+                                       , ("lib", "module lib; pub infixr 1 <$>; pub fun (<$>) f x = f x; infix 1 <|>;")
+                                       ]);
+            let mut symbols = SymbolTable::new();
+
+            let modules = load(&root, "main", &mut symbols).expect("load should succeed");
+
+            let expr = match modules[2].decls.iter().find(|d| matches!( d, Ast::Let { .. } )) {
+                Some(Ast::Let { expr, .. }) => expr,
+                x => panic!("expected let but found {:?}", x),
+            };
+
+            assert_eq!( show(expr, &symbols), expected, "{}", main );
+        }
+    }
+
+    #[test]
+    fn load_should_report_conflicting_fixity() {
+        let root = root("conflict", &[ ("main", "import a; import b;")
+                                     , ("a", "pub infixr 1 <$>;")
+                                     , ("b", "pub infixl 1 <$>;")
+                                     ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "main", &mut symbols).map(|_| ());
+
+        assert_eq!( r, Err(LoadError::ConflictingFixity { file : root.join("main.si")
+                                                        , op : "<$>".to_string()
+                                                        , span : Span { start : 10, end : 19, line : 1, column : 11 }
+                                                        }) );
+    }
+
+    fn show(e : &Expr, symbols : &SymbolTable) -> String {
+        match &e.kind {
+            ExprKind::Var(s) => symbols.resolve(*s).to_string(),
+            ExprKind::Binary { op, lhs, rhs } => 
+                format!("({} {} {})", show(lhs, symbols), symbols.resolve(op.symbol), show(rhs, symbols)),
+            x => panic!("show doesn't handle {:?}", x),
+        }
+    }
+
+    #[test]
+    fn load_should_report_cycle() {
+        let root = root("cycle", &[ ("a", "import b; let x = 1;")
+                                  , ("b", "import c;")
+                                  , ("c", "import b;")
+                                  ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "a", &mut symbols).map(|_| ());
+
+        assert_eq!( r, Err(LoadError::Cycle { modules : vec!["b".to_string(), "c".to_string()] }) );
+    }
+
+    #[test]
+    fn load_should_report_import_errors() {
+        let table = vec![ ("import lib (hidden);", "hidden")
+                        , ("import lib (missing);", "missing")
+                        , ("import lib; let x = Lib.hidden;", "hidden")
+                        , ("import lib; let x = match y { Lib.Hidden => 0 };", "Hidden")
+                        ];
+
+        for (main, expected) in table {
+            let root = root("exports", &[ ("main", main)
+                                        , ("lib", "module lib; fun hidden = 1; data Hidden = Hidden; pub let shown = 2;")
+                                        ]);
+            let mut symbols = SymbolTable::new();
+
+            let r = load(&root, "main", &mut symbols).map(|_| ());
+
+            assert!( matches!( &r, Err(LoadError::NotExported { module, name, .. }) if module == "lib" && name == expected ), "{}: {:?}", main, r );
+        }
+    }
+
+    #[test]
+    fn load_should_report_unknown_qualifier() {
+        let root = root("qualifier", &[ ("main", "import lib; let x = List.map;")
+                                      , ("lib", "pub fun map = 1;")
+                                      ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "main", &mut symbols).map(|_| ());
+
+        assert_eq!( r, Err(LoadError::UnknownModule { file : root.join("main.si")
+                                                    , qualifier : "List".to_string()
+                                                    , span : Span { start : 20, end : 28, line : 1, column : 21 }
+                                                    }) );
+    }
+
+    #[test]
+    fn load_should_report_ambiguous_qualifier() {
+        let root = root("ambiguous", &[ ("main", "import a.list; import b.list (map);")
+                                      , ("a.list", "pub fun map = 1;")
+                                      , ("b.list", "pub fun map = 2;")
+                                      ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "main", &mut symbols).map(|_| ());
+
+        assert_eq!( r, Err(LoadError::AmbiguousQualifier { file : root.join("main.si")
+                                                         , qualifier : "List".to_string()
+                                                         , modules : vec!["a.list".to_string(), "b.list".to_string()]
+                                                         , span : Span { start : 15, end : 35, line : 1, column : 16 }
+                                                         }) );
+    }
+
+    #[test]
+    fn load_should_allow_same_module_or_prelude_qualifier() {
+        let root = root("unambiguous", &[ ("main", "import a.list; import a.list (map); import b.prelude; import prelude;")
+                                        , ("a.list", "pub fun map = 1;")
+                                        , ("b.prelude", "pub fun map = 2;")
+                                        ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "main", &mut symbols).map(|_| ());
+
+        assert_eq!( r, Ok(()) );
+    }
+
+    #[test]
+    fn load_should_report_wrong_module_name() {
+        let root = root("name", &[ ("main", "import a.b;")
+                                 , ("a.b", "module a.c;")
+                                 ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "main", &mut symbols).map(|_| ());
+
+        assert_eq!( r, Err(LoadError::WrongName { file : root.join("a/b.si"), expected : "a.b".to_string(), found : "a.c".to_string() }) );
+    }
+
+    #[test]
+    fn load_should_report_missing_file() {
+        let root = root("missing", &[ ("main", "import nowhere;") ]);
+        let mut symbols = SymbolTable::new();
+
+        let r = load(&root, "main", &mut symbols).map(|_| ());
+
+        assert!( matches!( &r, Err(LoadError::Io { file, .. }) if *file == root.join("nowhere.si") ), "{:?}", r );
+    }
+}
@@ -1,6 +1,4 @@
 
-mod parsing;
-
 fn main() {
     println!("Hello, world!");
 }
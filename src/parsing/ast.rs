@@ -1,4 +1,156 @@
 
+use super::lexeme::Span;
+use super::symbol::Symbol;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Ident {
+    pub symbol : Symbol,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum Ast {
+    // Public declarations can be imported by other modules.
+    Fun { name : Ident, params : Vec<Ident>, body : Expr, public : bool, span : Span },
+    Let { name : Ident, expr : Expr, public : bool, span : Span },
+    Spec { name : Ident, type_params : Vec<Ident>, t : Type, span : Span },
+    Data { name : Ident, type_params : Vec<Ident>, constructors : Vec<Constructor>, public : bool, span : Span },
+    Fixity { assoc : Assoc, precedence : u8, op : Ident, public : bool, span : Span },
+    Module { path : Vec<Ident>, span : Span },
+    // Names are brought into scope unqualified.  Everything public in the module can 
+    // also be used qualified by the last part of the path, as in 'List.map' after 
+    // 'import std.list'.
+    Import { path : Vec<Ident>, names : Vec<Ident>, span : Span },
+}
+
+impl Ast {
+    pub fn span(&self) -> Span {
+        match self {
+            Ast::Fun { span, .. } => *span,
+            Ast::Let { span, .. } => *span,
+            Ast::Spec { span, .. } => *span,
+            Ast::Data { span, .. } => *span,
+            Ast::Fixity { span, .. } => *span,
+            Ast::Module { span, .. } => *span,
+            Ast::Import { span, .. } => *span,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Assoc {
+    Left,
+    Right,
+    NonAssoc,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Constructor {
+    pub name : Ident,
+    pub fields : Vec<Type>,
+    // One name for each field of a record constructor, and empty otherwise.
+    pub field_names : Vec<Ident>,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Expr {
+    pub kind : ExprKind,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ExprKind {
+    Bool(bool),
+    Integer(i64),
+    Decimal(f64),
+    String(String),
+    Char(char),
+    // Qualified names like 'List.map' are a single symbol.
+    Var(Symbol),
+    Call { fun : Box<Expr>, args : Vec<Expr> },
+    Binary { op : Ident, lhs : Box<Expr>, rhs : Box<Expr> },
+    Unary { op : Ident, expr : Box<Expr> },
+    Tuple(Vec<Expr>),
+    // Removed by desugaring along with '::'.
+    List(Vec<Expr>),
+    // The literal text and interpolated code of '"a ${b}"' in order, with the text as 
+    // Strings.  Removed by desugaring.
+    Interpolated(Vec<Expr>),
+    Match { expr : Box<Expr>, arms : Vec<MatchArm> },
+    // Statements are the let and spec declarations that come before the block's value.
+    Block { statements : Vec<Ast>, expr : Box<Expr> },
+    Constructor { name : Symbol, args : Vec<Expr> },
+    // Captures are the local variables from enclosing functions, lambdas, blocks and 
+    // match arms that the body refers to.  They are filled in after parsing.
+    Lambda { params : Vec<Ident>, body : Box<Expr>, captures : Vec<Symbol> },
+    // Removed by desugaring along with '&&', '||' and '!'.
+    If { cond : Box<Expr>, then : Box<Expr>, otherwise : Box<Expr> },
+    Record { name : Symbol, fields : Vec<FieldValue> },
+    Field { expr : Box<Expr>, name : Ident },
+    // '{ expr with field = value }' copies expr with the given fields replaced.
+    Update { expr : Box<Expr>, fields : Vec<FieldValue> },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct FieldValue {
+    pub name : Ident,
+    pub expr : Expr,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct MatchArm {
+    pub pattern : Pattern,
+    pub guard : Option<Expr>,
+    pub expr : Expr,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Pattern {
+    pub kind : PatternKind,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum PatternKind {
+    Wildcard,
+    Var(Symbol),
+    Bool(bool),
+    Integer(i64),
+    Decimal(f64),
+    String(String),
+    Char(char),
+    Tuple(Vec<Pattern>),
+    Constructor { name : Symbol, args : Vec<Pattern> },
+    Or(Vec<Pattern>),
+    As { name : Ident, pattern : Box<Pattern> },
+    // Fields that aren't listed match anything.
+    Record { name : Symbol, fields : Vec<FieldPattern> },
+    // '[a, b, ..rest]'.  Without a rest the list has to be exactly as long as the items.  
+    // Removed by desugaring.
+    List { items : Vec<Pattern>, rest : Option<Box<Pattern>> },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct FieldPattern {
+    pub name : Ident,
+    pub pattern : Pattern,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Type {
+    pub kind : TypeKind,
+    pub span : Span,
+}
 
-}
\ No newline at end of file
+#[derive(PartialEq, Debug, Clone)]
+pub enum TypeKind {
+    Generic(Symbol),
+    Concrete(Symbol),
+    Arrow { src : Box<Type>, dest : Box<Type> },
+    Tuple(Vec<Type>),
+    Applied { name : Symbol, args : Vec<Type> },
+}
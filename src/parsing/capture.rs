@@ -0,0 +1,274 @@
+
+use super::ast::{Ast, Expr, ExprKind, FieldValue, Pattern, PatternKind};
+use super::symbol::Symbol;
+
+/// Fills in the captures of every lambda in the declaration.  Only local variables
+/// are captured; names that aren't bound locally refer to top level declarations.
+pub fn fill_captures(decl : &mut Ast) {
+    let mut locals = vec![];
+
+    match decl {
+        Ast::Fun { params, body, .. } => {
+            locals.extend(params.iter().map(|p| p.symbol));
+            walk(body, &mut locals);
+        },
+        Ast::Let { expr, .. } => walk(expr, &mut locals),
+        Ast::Spec { .. } | Ast::Data { .. } | Ast::Fixity { .. } | Ast::Module { .. } | Ast::Import { .. } => { },
+    }
+}
+
+// Walks the expression with the local variables that are in scope.  Scopes are pushed
+// onto the end of locals and truncated off again afterward.
+fn walk(e : &mut Expr, locals : &mut Vec<Symbol>) {
+    match &mut e.kind {
+        ExprKind::Bool(_)
+        | ExprKind::Integer(_)
+        | ExprKind::Decimal(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_)
+        | ExprKind::Var(_) => { },
+        ExprKind::Call { fun, args } => {
+            walk(fun, locals);
+            args.iter_mut().for_each(|a| walk(a, locals));
+        },
+        ExprKind::Binary { lhs, rhs, .. } => {
+            walk(lhs, locals);
+            walk(rhs, locals);
+        },
+        ExprKind::Unary { expr, .. } => walk(expr, locals),
+        ExprKind::If { cond, then, otherwise } => {
+            walk(cond, locals);
+            walk(then, locals);
+            walk(otherwise, locals);
+        },
+        ExprKind::Tuple(es) | ExprKind::List(es) | ExprKind::Interpolated(es) => es.iter_mut().for_each(|e| walk(e, locals)),
+        ExprKind::Constructor { args, .. } => args.iter_mut().for_each(|a| walk(a, locals)),
+        ExprKind::Record { fields, .. } => fields.iter_mut().for_each(|f| walk(&mut f.expr, locals)),
+        ExprKind::Field { expr, .. } => walk(expr, locals),
+        ExprKind::Update { expr, fields } => {
+            walk(expr, locals);
+            fields.iter_mut().for_each(|f| walk(&mut f.expr, locals));
+        },
+        ExprKind::Match { expr, arms } => {
+            walk(expr, locals);
+            for arm in arms {
+                let scope = locals.len();
+                bind_pattern(&arm.pattern, locals);
+                if let Some(guard) = &mut arm.guard {
+                    walk(guard, locals);
+                }
+                walk(&mut arm.expr, locals);
+                locals.truncate(scope);
+            }
+        },
+        ExprKind::Block { statements, expr } => {
+            let scope = locals.len();
+            for statement in statements {
+                if let Ast::Let { name, expr, .. } = statement {
+                    walk(expr, locals);
+                    locals.push(name.symbol);
+                }
+            }
+            walk(expr, locals);
+            locals.truncate(scope);
+        },
+        ExprKind::Lambda { params, body, captures } => {
+            let mut bound = params.iter().map(|p| p.symbol).collect::<Vec<_>>();
+            let mut free = vec![];
+            free_variables(body, &mut bound, &mut free);
+
+            // The innermost binding of a name is the one that is captured, but either
+            // way it only needs to be captured once.
+            *captures = free.into_iter().filter(|s| locals.contains(s)).collect();
+
+            let scope = locals.len();
+            locals.extend(params.iter().map(|p| p.symbol));
+            walk(body, locals);
+            locals.truncate(scope);
+        },
+    }
+}
+
+fn bind_pattern(p : &Pattern, locals : &mut Vec<Symbol>) {
+    match &p.kind {
+        PatternKind::Var(s) => locals.push(*s),
+        PatternKind::As { name, pattern } => {
+            locals.push(name.symbol);
+            bind_pattern(pattern, locals);
+        },
+        PatternKind::Tuple(ps) => ps.iter().for_each(|p| bind_pattern(p, locals)),
+        PatternKind::Constructor { args, .. } => args.iter().for_each(|p| bind_pattern(p, locals)),
+        PatternKind::Record { fields, .. } => fields.iter().for_each(|f| bind_pattern(&f.pattern, locals)),
+        PatternKind::List { items, rest } => {
+            items.iter().for_each(|p| bind_pattern(p, locals));
+            if let Some(rest) = rest {
+                bind_pattern(rest, locals);
+            }
+        },
+        // Every alternative binds the same names.
+        PatternKind::Or(ps) => if let Some(p) = ps.first() { bind_pattern(p, locals) },
+        PatternKind::Wildcard
+        | PatternKind::Bool(_)
+        | PatternKind::Integer(_)
+        | PatternKind::Decimal(_)
+        | PatternKind::String(_)
+        | PatternKind::Char(_) => { },
+    }
+}
+
+// Collects the variables used in the expression that aren't in bound, in the order
+// they are first used.
+fn free_variables(e : &Expr, bound : &mut Vec<Symbol>, free : &mut Vec<Symbol>) {
+    match &e.kind {
+        ExprKind::Bool(_)
+        | ExprKind::Integer(_)
+        | ExprKind::Decimal(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_) => { },
+        ExprKind::Var(s) => {
+            if !bound.contains(s) && !free.contains(s) {
+                free.push(*s);
+            }
+        },
+        ExprKind::Call { fun, args } => {
+            free_variables(fun, bound, free);
+            args.iter().for_each(|a| free_variables(a, bound, free));
+        },
+        ExprKind::Binary { op, lhs, rhs } => {
+            free_variables(lhs, bound, free);
+            // A user defined operator can be bound locally like any other name.
+            if !bound.contains(&op.symbol) && !free.contains(&op.symbol) {
+                free.push(op.symbol);
+            }
+            free_variables(rhs, bound, free);
+        },
+        ExprKind::Unary { expr, .. } => free_variables(expr, bound, free),
+        ExprKind::If { cond, then, otherwise } => {
+            free_variables(cond, bound, free);
+            free_variables(then, bound, free);
+            free_variables(otherwise, bound, free);
+        },
+        ExprKind::Tuple(es) | ExprKind::List(es) | ExprKind::Interpolated(es) => es.iter().for_each(|e| free_variables(e, bound, free)),
+        ExprKind::Constructor { args, .. } => args.iter().for_each(|a| free_variables(a, bound, free)),
+        ExprKind::Record { fields, .. } => field_values(fields, bound, free),
+        ExprKind::Field { expr, .. } => free_variables(expr, bound, free),
+        ExprKind::Update { expr, fields } => {
+            free_variables(expr, bound, free);
+            field_values(fields, bound, free);
+        },
+        ExprKind::Match { expr, arms } => {
+            free_variables(expr, bound, free);
+            for arm in arms {
+                let scope = bound.len();
+                bind_pattern(&arm.pattern, bound);
+                if let Some(guard) = &arm.guard {
+                    free_variables(guard, bound, free);
+                }
+                free_variables(&arm.expr, bound, free);
+                bound.truncate(scope);
+            }
+        },
+        ExprKind::Block { statements, expr } => {
+            let scope = bound.len();
+            for statement in statements {
+                if let Ast::Let { name, expr, .. } = statement {
+                    free_variables(expr, bound, free);
+                    bound.push(name.symbol);
+                }
+            }
+            free_variables(expr, bound, free);
+            bound.truncate(scope);
+        },
+        ExprKind::Lambda { params, body, .. } => {
+            let scope = bound.len();
+            bound.extend(params.iter().map(|p| p.symbol));
+            free_variables(body, bound, free);
+            bound.truncate(scope);
+        },
+    }
+}
+
+fn field_values(fields : &[FieldValue], bound : &mut Vec<Symbol>, free : &mut Vec<Symbol>) {
+    fields.iter().for_each(|f| free_variables(&f.expr, bound, free))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lex::lex;
+    use super::super::parser::parse_module;
+    use super::super::symbol::SymbolTable;
+
+    // Finds the captures of each lambda in the source, outermost first.
+    fn captures(s : &str) -> Vec<Vec<String>> {
+        fn find(e : &Expr, symbols : &SymbolTable, out : &mut Vec<Vec<String>>) {
+            match &e.kind {
+                ExprKind::Lambda { body, captures, .. } => {
+                    out.push(captures.iter().map(|c| symbols.resolve(*c).to_string()).collect());
+                    find(body, symbols, out);
+                },
+                ExprKind::Call { fun, args } => {
+                    find(fun, symbols, out);
+                    args.iter().for_each(|a| find(a, symbols, out));
+                },
+                ExprKind::Binary { lhs, rhs, .. } => {
+                    find(lhs, symbols, out);
+                    find(rhs, symbols, out);
+                },
+                ExprKind::Match { arms, .. } => arms.iter().for_each(|a| find(&a.expr, symbols, out)),
+                ExprKind::Block { statements, expr } => {
+                    for statement in statements {
+                        if let Ast::Let { expr, .. } = statement {
+                            find(expr, symbols, out);
+                        }
+                    }
+                    find(expr, symbols, out);
+                },
+                _ => { },
+            }
+        }
+
+        let mut symbols = SymbolTable::new();
+        let tokens = lex(s, &mut symbols).expect("lex should succeed");
+        let decls = parse_module(tokens, &mut symbols).expect("parse should succeed");
+
+        let mut out = vec![];
+        for decl in &decls {
+            match decl {
+                Ast::Fun { body, .. } => find(body, &symbols, &mut out),
+                Ast::Let { expr, .. } => find(expr, &symbols, &mut out),
+                _ => { },
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn fill_captures_should_capture_locals() {
+        let table = vec![ ("let f = \\x => x;", vec![vec![]])
+                        , ("fun f a = \\x => a + x;", vec![vec!["a"]])
+                        , ("fun f a = \\x => g a x;", vec![vec!["a"]])
+                        , ("let f = { let a = 1; \\x => a + x };", vec![vec!["a"]])
+                        , ("fun f a b = \\x => b + a + b;", vec![vec!["b", "a"]])
+                        , ("fun f a = \\x => \\y => a + x + y;", vec![vec!["a"], vec!["a", "x"]])
+                        , ("fun f a = \\a => a;", vec![vec![]])
+                        , ("fun f o = match o { Some(v) => \\x => v + x, None => \\x => o };", vec![vec!["v"], vec!["o"]])
+                        , ("fun f o = match o { y @ (A | B) => \\x => y };", vec![vec!["y"]])
+                        , ("fun f a = \\x => { let a = 1; a };", vec![vec![]])
+                        , ("fun f a = \\x => { let b = a; b };", vec![vec!["a"]])
+                        , ("fun f p = \\x => { p with age = x.age };", vec![vec!["p"]])
+                        , ("fun f concat = \\x => \"a${x}\";", vec![vec![]])
+                        , ("fun f a = \\x => \"${a} ${x}\";", vec![vec!["a"]])
+                        , ("fun f o = match o { P { a = b, c } => \\x => b + c + a };", vec![vec!["b", "c"]])
+                        , ("fun f o = match o { [a, ..rest] => \\x => [a, x] :: rest };", vec![vec!["a", "rest"]])
+                        , ("fun f a = { let (<$>) = a; \\x => x <$> x };", vec![vec!["<$>"]])
+                        , ("fun f a = \\x => x <$> a;", vec![vec!["a"]])
+                        ];
+
+        for (s, expected) in table {
+            let expected = expected.into_iter().map(|cs| cs.into_iter().map(|c| c.to_string()).collect::<Vec<_>>()).collect::<Vec<_>>();
+
+            assert_eq!( captures(s), expected, "{}", s );
+        }
+    }
+}
@@ -0,0 +1,473 @@
+
+use super::lexeme::{Lexeme, Span};
+use super::parser::{Expected, Input, ParseError, Parser};
+
+/// Parses a single token, returning its span.
+pub fn token(lexeme : Lexeme) -> Token {
+    Token { lexeme }
+}
+
+pub struct Token {
+    lexeme : Lexeme,
+}
+
+impl Parser for Token {
+    type Output = Span;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.check(&self.lexeme)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Span, ParseError> {
+        input.expect(self.lexeme.clone())
+    }
+}
+
+/// Turns a parsing function into a parser.  `first` says which tokens the function
+/// can start with and `expected` is how it is described in errors.
+pub fn rule<T>(parse : fn(&mut Input) -> Result<T, ParseError>, first : fn(&Lexeme) -> bool, expected : Expected) -> Rule<T> {
+    Rule { parse, first, expected }
+}
+
+pub struct Rule<T> {
+    parse : fn(&mut Input) -> Result<T, ParseError>,
+    first : fn(&Lexeme) -> bool,
+    expected : Expected,
+}
+
+impl<T> Parser for Rule<T> {
+    type Output = T;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.expecting(self.expected.clone());
+        input.peek().is_some_and(self.first)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<T, ParseError> {
+        (self.parse)(input)
+    }
+}
+
+/// Parses `a` and then `b`.
+pub fn seq<A : Parser, B : Parser>(a : A, b : B) -> Seq<A, B> {
+    Seq { a, b }
+}
+
+pub struct Seq<A, B> {
+    a : A,
+    b : B,
+}
+
+impl<A : Parser, B : Parser> Parser for Seq<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn usable(&self, input : &mut Input) -> bool {
+        self.a.usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        let a = self.a.parse(input)?;
+        let b = self.b.parse(input)?;
+        Ok((a, b))
+    }
+}
+
+/// Parses with `a` when it is usable and with `b` otherwise.  If `a` fails without
+/// consuming anything then `b` gets a try as well.
+pub fn alt<A : Parser, B : Parser<Output = A::Output>>(a : A, b : B) -> Alt<A, B> {
+    Alt { a, b }
+}
+
+pub struct Alt<A, B> {
+    a : A,
+    b : B,
+}
+
+impl<A : Parser, B : Parser<Output = A::Output>> Parser for Alt<A, B> {
+    type Output = A::Output;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        // Both are asked so that errors list everything that could have come next.
+        let a = self.a.usable(input);
+        let b = self.b.usable(input);
+        a || b
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        let rp = input.restore_point();
+
+        if self.a.usable(input) {
+            match self.a.parse(input) {
+                Err(_) if input.restore_point() == rp && self.b.usable(input) => { },
+                r => return r,
+            }
+        }
+
+        self.b.parse(input)
+    }
+}
+
+/// Parses `p` for as long as it is usable.
+pub fn many<P : Parser>(p : P) -> Many<P> {
+    Many { p }
+}
+
+pub struct Many<P> {
+    p : P,
+}
+
+impl<P : Parser> Parser for Many<P> {
+    type Output = Vec<P::Output>;
+
+    fn usable(&self, _input : &mut Input) -> bool {
+        true
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        let mut items = vec![];
+        while self.p.usable(input) {
+            items.push(self.p.parse(input)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Parses zero or more `item`s separated by `sep`, allowing a trailing `sep`.
+pub fn sep_by<P : Parser>(item : P, sep : Lexeme) -> SepBy<P> {
+    SepBy { item, sep, at_least_one : false }
+}
+
+/// Parses one or more `item`s separated by `sep` with no trailing `sep`.
+pub fn sep_by1<P : Parser>(item : P, sep : Lexeme) -> SepBy<P> {
+    SepBy { item, sep, at_least_one : true }
+}
+
+pub struct SepBy<P> {
+    item : P,
+    sep : Lexeme,
+    at_least_one : bool,
+}
+
+impl<P : Parser> Parser for SepBy<P> {
+    type Output = Vec<P::Output>;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        !self.at_least_one || self.item.usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        let mut items = vec![];
+
+        if !self.at_least_one && !self.item.usable(input) {
+            return Ok(items);
+        }
+
+        loop {
+            items.push(self.item.parse(input)?);
+
+            if !input.check(&self.sep) {
+                return Ok(items);
+            }
+            input.next();
+
+            if !self.at_least_one && !self.item.usable(input) {
+                return Ok(items);
+            }
+        }
+    }
+}
+
+/// Parses `p` if it is usable.
+pub fn optional<P : Parser>(p : P) -> Optional<P> {
+    Optional { p }
+}
+
+pub struct Optional<P> {
+    p : P,
+}
+
+impl<P : Parser> Parser for Optional<P> {
+    type Output = Option<P::Output>;
+
+    fn usable(&self, _input : &mut Input) -> bool {
+        true
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        if self.p.usable(input) {
+            Ok(Some(self.p.parse(input)?))
+        }
+        else {
+            Ok(None)
+        }
+    }
+}
+
+/// Parses `p` between `open` and `close`.  The span covers both delimiters.
+pub fn delimited<P : Parser>(open : Lexeme, p : P, close : Lexeme) -> Delimited<P> {
+    Delimited { open, p, close }
+}
+
+pub struct Delimited<P> {
+    open : Lexeme,
+    p : P,
+    close : Lexeme,
+}
+
+impl<P : Parser> Parser for Delimited<P> {
+    type Output = (P::Output, Span);
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.check(&self.open)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        let start = input.expect(self.open.clone())?;
+        let r = self.p.parse(input)?;
+        let end = input.expect(self.close.clone())?;
+        Ok((r, Span { start : start.start, end : end.end, line : start.line, column : start.column }))
+    }
+}
+
+/// Transforms the output of `p` with `f`.
+pub fn map<P : Parser, T, F : Fn(P::Output) -> T + Sync>(p : P, f : F) -> Map<P, F> {
+    Map { p, f }
+}
+
+pub struct Map<P, F> {
+    p : P,
+    f : F,
+}
+
+impl<P : Parser, T, F : Fn(P::Output) -> T + Sync> Parser for Map<P, F> {
+    type Output = T;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        self.p.usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<T, ParseError> {
+        self.p.parse(input).map(&self.f)
+    }
+}
+
+/// Reports `expected` instead of whatever `p` was looking for when `p` fails before
+/// consuming anything.
+pub fn label<P : Parser>(p : P, expected : Expected) -> Label<P> {
+    Label { p, expected }
+}
+
+pub struct Label<P> {
+    p : P,
+    expected : Expected,
+}
+
+impl<P : Parser> Parser for Label<P> {
+    type Output = P::Output;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.expecting(self.expected.clone());
+        self.p.usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<P::Output, ParseError> {
+        let rp = input.restore_point();
+        match self.p.parse(input) {
+            Err(mut e) if input.restore_point() == rp => {
+                e.expected = vec![self.expected.clone()];
+                Err(e)
+            },
+            r => r,
+        }
+    }
+}
+
+/// Puts the input back where it was when `p` fails so that another parser can try
+/// from the same place.
+pub fn attempt<P : Parser>(p : P) -> Attempt<P> {
+    Attempt { p }
+}
+
+pub struct Attempt<P> {
+    p : P,
+}
+
+impl<P : Parser> Parser for Attempt<P> {
+    type Output = P::Output;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        self.p.usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<P::Output, ParseError> {
+        let rp = input.restore_point();
+        let r = self.p.parse(input);
+        if r.is_err() {
+            input.restore(rp);
+        }
+        r
+    }
+}
+
+/// Parses `p`, except that when the input is recovering from errors and `p` fails, the
+/// error is recorded and the rest of the item up to `separator` is skipped.  Skipped 
+/// items come out as None.
+pub fn recover<P : Parser>(p : P, separator : Lexeme) -> Recover<P> {
+    Recover { p, separator }
+}
+
+pub struct Recover<P> {
+    p : P,
+    separator : Lexeme,
+}
+
+impl<P : Parser> Parser for Recover<P> {
+    type Output = Option<P::Output>;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        self.p.usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        let start = input.restore_point();
+        match self.p.parse(input) {
+            Ok(x) => Ok(Some(x)),
+            Err(e) => {
+                input.recover(e, start, &self.separator)?;
+                Ok(None)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lex::lex;
+    use super::super::symbol::SymbolTable;
+
+    fn integer(input : &mut Input) -> Result<i64, ParseError> {
+        match input.peek() {
+            Some(&Lexeme::Integer(i)) => { input.next(); Ok(i) },
+            _ => Err(input.error(Expected::Expr)),
+        }
+    }
+
+    fn is_integer(lexeme : &Lexeme) -> bool {
+        matches!( lexeme, Lexeme::Integer(_) )
+    }
+
+    fn ints() -> Rule<i64> {
+        rule(integer, is_integer, Expected::Expr)
+    }
+
+    fn run<P : Parser>(p : P, s : &str) -> (Result<P::Output, ParseError>, Option<Lexeme>) {
+        let mut symbols = SymbolTable::new();
+        let tokens = lex(s, &mut symbols).expect("lex should succeed");
+        let mut input = Input::new(tokens, &mut symbols);
+
+        let r = p.parse(&mut input);
+        (r, input.peek().cloned())
+    }
+
+    #[test]
+    fn sep_by_should_allow_trailing_separator() {
+        let table = vec![ ("", vec![])
+                        , ("1", vec![1])
+                        , ("1, 2", vec![1, 2])
+                        , ("1, 2,", vec![1, 2])
+                        ];
+
+        for (s, expected) in table {
+            let (r, rest) = run(sep_by(ints(), Lexeme::Comma), s);
+
+            assert_eq!( r, Ok(expected) );
+            assert_eq!( rest, None );
+        }
+    }
+
+    #[test]
+    fn sep_by1_should_require_item_after_separator() {
+        let (r, _) = run(sep_by1(ints(), Lexeme::OrBar), "1 | 2 |");
+
+        assert!( matches!( r, Err(ParseError { found : None, .. }) ) );
+
+        let (r, _) = run(sep_by1(ints(), Lexeme::OrBar), "");
+
+        assert!( r.is_err() );
+    }
+
+    #[test]
+    fn delimited_should_span_delimiters() {
+        let (r, rest) = run(delimited(Lexeme::LParen, sep_by(ints(), Lexeme::Comma), Lexeme::RParen), "(1, 2) 3");
+
+        assert_eq!( r, Ok((vec![1, 2], Span { start : 0, end : 6, line : 1, column : 1 })) );
+        assert_eq!( rest, Some(Lexeme::Integer(3)) );
+    }
+
+    #[test]
+    fn delimited_should_report_expected_close() {
+        let (r, _) = run(delimited(Lexeme::LParen, sep_by(ints(), Lexeme::Comma), Lexeme::RParen), "(1 ;");
+
+        let e = r.expect_err("delimited should fail");
+
+        assert_eq!( e.expected, vec![Expected::Lexeme(Lexeme::Comma), Expected::Lexeme(Lexeme::RParen)] );
+        assert_eq!( e.found, Some(Lexeme::SemiColon) );
+    }
+
+    #[test]
+    fn seq_many_optional_and_map_should_compose() {
+        let p = map( seq(token(Lexeme::Let), seq(many(ints()), optional(token(Lexeme::SemiColon))))
+                   , |(_, (is, semi))| (is.iter().sum::<i64>(), semi.is_some())
+                   );
+
+        let (r, _) = run(&p, "let 1 2 3;");
+        assert_eq!( r, Ok((6, true)) );
+
+        let (r, _) = run(&p, "let");
+        assert_eq!( r, Ok((0, false)) );
+    }
+
+    #[test]
+    fn alt_should_pick_usable_parser() {
+        let p = alt(map(token(Lexeme::Let), |_| 0), ints());
+
+        assert_eq!( run(&p, "let").0, Ok(0) );
+        assert_eq!( run(&p, "5").0, Ok(5) );
+
+        let e = run(&p, ";").0.expect_err("alt should fail");
+        assert_eq!( e.expected, vec![Expected::Lexeme(Lexeme::Let), Expected::Expr] );
+    }
+
+    #[test]
+    fn attempt_should_backtrack_for_alt() {
+        // Both start with 'let', so the first has to give the input back when it fails.
+        let p = alt( attempt(map(seq(token(Lexeme::Let), token(Lexeme::Fun)), |_| "fun"))
+                   , map(seq(token(Lexeme::Let), ints()), |_| "int")
+                   );
+
+        assert_eq!( run(&p, "let fun").0, Ok("fun") );
+        assert_eq!( run(&p, "let 5").0, Ok("int") );
+    }
+
+    #[test]
+    fn alt_should_not_backtrack_without_attempt() {
+        let p = alt( map(seq(token(Lexeme::Let), token(Lexeme::Fun)), |_| "fun")
+                   , map(seq(token(Lexeme::Let), ints()), |_| "int")
+                   );
+
+        assert!( run(&p, "let 5").0.is_err() );
+    }
+
+    #[test]
+    fn label_should_replace_expected_set() {
+        let p = label(seq(token(Lexeme::LParen), ints()), Expected::Type);
+
+        let e = run(&p, "5").0.expect_err("label should fail");
+        assert_eq!( e.expected, vec![Expected::Type] );
+
+        // Once something has been consumed the error is left alone.
+        let e = run(&p, "( ;").0.expect_err("label should fail");
+        assert_eq!( e.expected, vec![Expected::Expr] );
+    }
+}
@@ -0,0 +1,292 @@
+
+use super::ast::{Ast, Expr, ExprKind, MatchArm, Pattern, PatternKind};
+use super::lexeme::Span;
+use super::prelude::{CONCAT, CONS, NIL};
+use super::symbol::SymbolTable;
+
+/// Rewrites if expressions and the boolean operators '&&', '||' and '!' into matches
+/// on Bool.  The right hand side of '&&' and '||' ends up in a match arm, so it is only
+/// evaluated when it's needed.  List literals, '::' and list patterns become the Cons 
+/// and Nil constructors of the prelude's List, and interpolated strings become calls to
+/// the prelude's concat.  These are qualified so that the module's own names can't 
+/// capture them.  The loader runs this on every module.
+pub fn desugar(decl : &mut Ast, symbols : &mut SymbolTable) {
+    match decl {
+        Ast::Fun { body, .. } => walk(body, symbols),
+        Ast::Let { expr, .. } => walk(expr, symbols),
+        Ast::Spec { .. } | Ast::Data { .. } | Ast::Fixity { .. } | Ast::Module { .. } | Ast::Import { .. } => { },
+    }
+}
+
+fn walk(e : &mut Expr, symbols : &mut SymbolTable) {
+    match &mut e.kind {
+        ExprKind::Bool(_)
+        | ExprKind::Integer(_)
+        | ExprKind::Decimal(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_)
+        | ExprKind::Var(_) => { },
+        ExprKind::Call { fun, args } => {
+            walk(fun, symbols);
+            args.iter_mut().for_each(|a| walk(a, symbols));
+        },
+        ExprKind::Binary { lhs, rhs, .. } => {
+            walk(lhs, symbols);
+            walk(rhs, symbols);
+        },
+        ExprKind::Unary { expr, .. } => walk(expr, symbols),
+        ExprKind::If { cond, then, otherwise } => {
+            walk(cond, symbols);
+            walk(then, symbols);
+            walk(otherwise, symbols);
+        },
+        ExprKind::Tuple(es) | ExprKind::List(es) | ExprKind::Interpolated(es) => es.iter_mut().for_each(|e| walk(e, symbols)),
+        ExprKind::Constructor { args, .. } => args.iter_mut().for_each(|a| walk(a, symbols)),
+        ExprKind::Record { fields, .. } => fields.iter_mut().for_each(|f| walk(&mut f.expr, symbols)),
+        ExprKind::Field { expr, .. } => walk(expr, symbols),
+        ExprKind::Update { expr, fields } => {
+            walk(expr, symbols);
+            fields.iter_mut().for_each(|f| walk(&mut f.expr, symbols));
+        },
+        ExprKind::Match { expr, arms } => {
+            walk(expr, symbols);
+            for arm in arms {
+                walk_pattern(&mut arm.pattern, symbols);
+                if let Some(guard) = &mut arm.guard {
+                    walk(guard, symbols);
+                }
+                walk(&mut arm.expr, symbols);
+            }
+        },
+        ExprKind::Block { statements, expr } => {
+            statements.iter_mut().for_each(|s| desugar(s, symbols));
+            walk(expr, symbols);
+        },
+        ExprKind::Lambda { body, .. } => walk(body, symbols),
+    }
+
+    // The children are already desugared, so rewriting this node is all that's left.
+    let span = e.span;
+    let kind = std::mem::replace(&mut e.kind, ExprKind::Bool(false));
+    e.kind = match kind {
+        ExprKind::If { cond, then, otherwise } => bool_match(*cond, *then, *otherwise),
+        ExprKind::Binary { op, lhs, rhs } if symbols.resolve(op.symbol) == "&&" =>
+            bool_match(*lhs, *rhs, bool(false, span)),
+        ExprKind::Binary { op, lhs, rhs } if symbols.resolve(op.symbol) == "||" =>
+            bool_match(*lhs, bool(true, span), *rhs),
+        ExprKind::Unary { op, expr } if symbols.resolve(op.symbol) == "!" =>
+            bool_match(*expr, bool(false, span), bool(true, span)),
+        ExprKind::Binary { op, lhs, rhs } if symbols.resolve(op.symbol) == "::" =>
+            ExprKind::Constructor { name : symbols.intern(CONS), args : vec![*lhs, *rhs] },
+        // Every cell gets the span of the whole literal.
+        ExprKind::List(es) => {
+            let nil = Expr { kind : ExprKind::Constructor { name : symbols.intern(NIL), args : vec![] }, span };
+            let cons = symbols.intern(CONS);
+            es.into_iter().rev().fold(nil, |tail, e| Expr { kind : ExprKind::Constructor { name : cons, args : vec![e, tail] }, span }).kind
+        },
+        // "a ${b} c" becomes concat(concat("a ", b), " c").  Starting from a string means 
+        // that "${b}" is still a string.
+        ExprKind::Interpolated(mut es) => {
+            if !matches!( es.first(), Some(Expr { kind : ExprKind::String(_), .. }) ) {
+                es.insert(0, Expr { kind : ExprKind::String(String::new()), span });
+            }
+
+            let concat = symbols.intern(CONCAT);
+            let mut es = es.into_iter();
+            let first = es.next().unwrap();
+
+            es.fold(first, |acc, e| {
+                let fun = Expr { kind : ExprKind::Var(concat), span };
+                Expr { kind : ExprKind::Call { fun : Box::new(fun), args : vec![acc, e] }, span }
+            }).kind
+        },
+        kind => kind,
+    };
+}
+
+fn walk_pattern(p : &mut Pattern, symbols : &mut SymbolTable) {
+    match &mut p.kind {
+        PatternKind::Wildcard
+        | PatternKind::Var(_)
+        | PatternKind::Bool(_)
+        | PatternKind::Integer(_)
+        | PatternKind::Decimal(_)
+        | PatternKind::String(_)
+        | PatternKind::Char(_) => { },
+        PatternKind::Tuple(ps) | PatternKind::Or(ps) => ps.iter_mut().for_each(|p| walk_pattern(p, symbols)),
+        PatternKind::Constructor { args, .. } => args.iter_mut().for_each(|p| walk_pattern(p, symbols)),
+        PatternKind::As { pattern, .. } => walk_pattern(pattern, symbols),
+        PatternKind::Record { fields, .. } => fields.iter_mut().for_each(|f| walk_pattern(&mut f.pattern, symbols)),
+        PatternKind::List { items, rest } => {
+            items.iter_mut().for_each(|p| walk_pattern(p, symbols));
+            if let Some(rest) = rest {
+                walk_pattern(rest, symbols);
+            }
+        },
+    }
+
+    let span = p.span;
+    let kind = std::mem::replace(&mut p.kind, PatternKind::Wildcard);
+    p.kind = match kind {
+        PatternKind::List { items, rest } => {
+            let tail = match rest {
+                Some(rest) => *rest,
+                None => Pattern { kind : PatternKind::Constructor { name : symbols.intern(NIL), args : vec![] }, span },
+            };
+            let cons = symbols.intern(CONS);
+            items.into_iter().rev().fold(tail, |tail, p| Pattern { kind : PatternKind::Constructor { name : cons, args : vec![p, tail] }, span }).kind
+        },
+        kind => kind,
+    };
+}
+
+// 'match cond { true => on_true, false => on_false }'.  The arms and patterns take their
+// spans from the expressions they came from.
+fn bool_match(cond : Expr, on_true : Expr, on_false : Expr) -> ExprKind {
+    let arm = |value, expr : Expr| MatchArm { pattern : Pattern { kind : PatternKind::Bool(value), span : expr.span }
+                                            , guard : None
+                                            , span : expr.span
+                                            , expr
+                                            };
+
+    ExprKind::Match { expr : Box::new(cond), arms : vec![arm(true, on_true), arm(false, on_false)] }
+}
+
+fn bool(value : bool, span : Span) -> Expr {
+    Expr { kind : ExprKind::Bool(value), span }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lex::lex;
+    use super::super::parser::parse_module;
+
+    // Prints a desugared expression with matches on Bool written as '(c ? a : b)'.
+    fn show(e : &Expr, symbols : &SymbolTable) -> String {
+        match &e.kind {
+            ExprKind::Bool(b) => b.to_string(),
+            ExprKind::Integer(i) => i.to_string(),
+            ExprKind::String(s) => format!("{:?}", s),
+            ExprKind::Var(s) => symbols.resolve(*s).to_string(),
+            ExprKind::Constructor { name, args } if args.is_empty() => symbols.resolve(*name).to_string(),
+            ExprKind::Constructor { name, args } =>
+                format!("{}({})", symbols.resolve(*name), args.iter().map(|a| show(a, symbols)).collect::<Vec<_>>().join(", ")),
+            ExprKind::Call { fun, args } =>
+                format!("{}({})", show(fun, symbols), args.iter().map(|a| show(a, symbols)).collect::<Vec<_>>().join(", ")),
+            ExprKind::Binary { op, lhs, rhs } =>
+                format!("({} {} {})", show(lhs, symbols), symbols.resolve(op.symbol), show(rhs, symbols)),
+            ExprKind::Unary { op, expr } => format!("({}{})", symbols.resolve(op.symbol), show(expr, symbols)),
+            ExprKind::Block { statements, expr } if statements.is_empty() => format!("{{{}}}", show(expr, symbols)),
+            ExprKind::Lambda { params, body, .. } =>
+                format!("(\\{} => {})", params.iter().map(|p| symbols.resolve(p.symbol)).collect::<Vec<_>>().join(" "), show(body, symbols)),
+            ExprKind::Match { expr, arms } => {
+                assert_eq!( arms.len(), 2 );
+                assert_eq!( arms[0].pattern.kind, PatternKind::Bool(true) );
+                assert_eq!( arms[1].pattern.kind, PatternKind::Bool(false) );
+                format!("({} ? {} : {})", show(expr, symbols), show(&arms[0].expr, symbols), show(&arms[1].expr, symbols))
+            },
+            x => panic!("show doesn't handle {:?}", x),
+        }
+    }
+
+    fn desugar_expr(s : &str) -> String {
+        let mut symbols = SymbolTable::new();
+        let tokens = lex(s, &mut symbols).expect("lex should succeed");
+        let mut decls = parse_module(tokens, &mut symbols).expect("parse should succeed");
+
+        decls.iter_mut().for_each(|d| desugar(d, &mut symbols));
+
+        match &decls[0] {
+            Ast::Let { expr, .. } => show(expr, &symbols),
+            x => panic!("expected let but found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn desugar_should_rewrite_to_bool_match() {
+        let table = vec![ ("let x = if a { b } else { c };", "(a ? {b} : {c})")
+                        , ("let x = if a { b } else if c { d } else { e };", "(a ? {b} : (c ? {d} : {e}))")
+                        , ("let x = a && b;", "(a ? b : false)")
+                        , ("let x = a || b;", "(a ? true : b)")
+                        , ("let x = !a;", "(a ? false : true)")
+                        , ("let x = !a && b || c;", "(((a ? false : true) ? b : false) ? true : c)")
+                        , ("let x = a == b && f c;", "((a == b) ? f(c) : false)")
+                        , ("let x = if !a { b } else { c + 1 };", "((a ? false : true) ? {b} : {(c + 1)})")
+                        , ("let x = \\a => a && b;", "(\\a => (a ? b : false))")
+                        , ("let x = -a + b;", "((-a) + b)")
+                        , ("let x = [];", "Prelude.Nil")
+                        , ("let x = [1, 2];", "Prelude.Cons(1, Prelude.Cons(2, Prelude.Nil))")
+                        , ("let x = x :: xs;", "Prelude.Cons(x, xs)")
+                        , ("let x = 1 :: 2 :: [3];", "Prelude.Cons(1, Prelude.Cons(2, Prelude.Cons(3, Prelude.Nil)))")
+                        , ("let x = [a && b, [c]];", "Prelude.Cons((a ? b : false), Prelude.Cons(Prelude.Cons(c, Prelude.Nil), Prelude.Nil))")
+                        , ("let x = f x :: xs;", "Prelude.Cons(f(x), xs)")
+                        , ("let x = \"a ${b} c\";", "Prelude.concat(Prelude.concat(\"a \", b), \" c\")")
+                        , ("let x = \"${b}\";", "Prelude.concat(\"\", b)")
+                        , ("let x = \"${a || b}${[c]}\";", "Prelude.concat(Prelude.concat(\"\", (a ? true : b)), Prelude.Cons(c, Prelude.Nil))")
+                        ];
+
+        for (s, expected) in table {
+            assert_eq!( desugar_expr(s), expected, "{}", s );
+        }
+    }
+
+    fn show_pattern(p : &Pattern, symbols : &SymbolTable) -> String {
+        match &p.kind {
+            PatternKind::Wildcard => "_".to_string(),
+            PatternKind::Var(s) => symbols.resolve(*s).to_string(),
+            PatternKind::Integer(i) => i.to_string(),
+            PatternKind::Constructor { name, args } if args.is_empty() => symbols.resolve(*name).to_string(),
+            PatternKind::Constructor { name, args } =>
+                format!("{}({})", symbols.resolve(*name), args.iter().map(|a| show_pattern(a, symbols)).collect::<Vec<_>>().join(", ")),
+            PatternKind::Tuple(ps) => format!("({})", ps.iter().map(|a| show_pattern(a, symbols)).collect::<Vec<_>>().join(", ")),
+            x => panic!("show_pattern doesn't handle {:?}", x),
+        }
+    }
+
+    #[test]
+    fn desugar_should_rewrite_list_patterns() {
+        let table = vec![ ("[]", "Prelude.Nil")
+                        , ("[a]", "Prelude.Cons(a, Prelude.Nil)")
+                        , ("[a, b,]", "Prelude.Cons(a, Prelude.Cons(b, Prelude.Nil))")
+                        , ("[a, ..rest]", "Prelude.Cons(a, rest)")
+                        , ("[a, 1, ..]", "Prelude.Cons(a, Prelude.Cons(1, _))")
+                        , ("[..all]", "all")
+                        , ("[[a], ..]", "Prelude.Cons(Prelude.Cons(a, Prelude.Nil), _)")
+                        , ("Some([a])", "Some(Prelude.Cons(a, Prelude.Nil))")
+                        , ("(x, [y])", "(x, Prelude.Cons(y, Prelude.Nil))")
+                        ];
+
+        for (p, expected) in table {
+            let mut symbols = SymbolTable::new();
+            let s = format!("let x = match y {{ {} => 0 }};", p);
+            let tokens = lex(&s, &mut symbols).expect("lex should succeed");
+            let mut decls = parse_module(tokens, &mut symbols).expect("parse should succeed");
+
+            desugar(&mut decls[0], &mut symbols);
+
+            match &decls[0] {
+                Ast::Let { expr : Expr { kind : ExprKind::Match { arms, .. }, .. }, .. } => 
+                    assert_eq!( show_pattern(&arms[0].pattern, &symbols), expected, "{}", p ),
+                x => panic!("expected match but found {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn desugar_should_rewrite_block_statements() {
+        let mut symbols = SymbolTable::new();
+        let tokens = lex("fun f a = { let y = a || b; y };", &mut symbols).expect("lex should succeed");
+        let mut decls = parse_module(tokens, &mut symbols).expect("parse should succeed");
+
+        decls.iter_mut().for_each(|d| desugar(d, &mut symbols));
+
+        match &decls[0] {
+            Ast::Fun { body : Expr { kind : ExprKind::Block { statements, .. }, .. }, .. } => match &statements[0] {
+                Ast::Let { expr, .. } => assert_eq!( show(expr, &symbols), "(a ? true : b)" ),
+                x => panic!("expected let but found {:?}", x),
+            },
+            x => panic!("expected fun with block but found {:?}", x),
+        }
+    }
+}
@@ -1,389 +1,2025 @@
 
-use std::str::CharIndices;
-use std::iter::Peekable; 
-
-use super::lexeme::Lexeme;
-
-
-pub fn lex(s : &str) -> Result<Vec<Lexeme>, usize> {
-
-    let mut input = Input { cs : s.char_indices().peekable() };
-
-    let lexers : [&dyn Lexer; 20] = [ &JunkLexer{}
-                                    , &BoolLexer{}
-                                    , &NumberLexer{}
-                                    , &StringLexer{}
-                                    , &PunctLexer{ punct : ['('], lexeme : Lexeme::LParen }
-                                    , &PunctLexer{ punct : [')'], lexeme : Lexeme::RParen }
-                                    , &PunctLexer{ punct : ['<'], lexeme : Lexeme::LAngle }
-                                    , &PunctLexer{ punct : ['>'], lexeme : Lexeme::RAngle }
-                                    , &PunctLexer{ punct : ['{'], lexeme : Lexeme::LCurl }
-                                    , &PunctLexer{ punct : ['}'], lexeme : Lexeme::RCurl }
-                                    , &PunctLexer{ punct : ['|'], lexeme : Lexeme::OrBar }
-                                    , &PunctLexer{ punct : [';'], lexeme : Lexeme::SemiColon }
-                                    , &PunctLexer{ punct : [','], lexeme : Lexeme::Comma }
-                                    , &PunctLexer{ punct : ['='], lexeme : Lexeme::Equal }
-                                    , &PunctLexer{ punct : ['=', '>'], lexeme : Lexeme::RightDoubleArrow }
-                                    , &KeywordLexer{ keyword : "fun", lexeme : Lexeme::Fun }
-                                    , &KeywordLexer{ keyword : "let", lexeme : Lexeme::Let }
-                                    , &KeywordLexer{ keyword : "spec", lexeme : Lexeme::Spec }
-                                    , &KeywordLexer{ keyword : "data", lexeme : Lexeme::Data }
-                                    , &SymbolLexer{} 
-                                    ];
-
-    let mut ret = vec![];
+use std::io::{ErrorKind, Read};
+use std::sync::OnceLock;
+
+use super::lexeme::{Lexeme, StringPart, Token, Span, Trivia, TriviaKind, LosslessToken, LosslessTokens};
+use super::symbol::SymbolTable;
+
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum LexError {
+    UnterminatedComment { start : usize, text : String },
+    UnterminatedString { start : usize, text : String },
+    UnterminatedChar { start : usize, text : String },
+    BadEscape { index : usize, text : String },
+    MalformedNumber { index : usize, text : String },
+    NumberOverflow { index : usize, text : String },
+    UnexpectedChar { index : usize, c : char },
+    UnexpectedEof { index : usize },
+    InvalidUtf8 { index : usize },
+    Io { index : usize, message : String },
+}
 
-    for lexer in lexers {
-        if lexer.usable(&mut input) {
-            match lexer.lex(&mut input) {
-                Ok(lexeme) => ret.push(lexeme),
-                Err(index) => return Err(index),
-            }
+impl LexError {
+    fn moved(self, offset : usize) -> LexError {
+        use LexError::*;
+        match self {
+            UnterminatedComment { start, text } => UnterminatedComment { start : start + offset, text },
+            UnterminatedString { start, text } => UnterminatedString { start : start + offset, text },
+            UnterminatedChar { start, text } => UnterminatedChar { start : start + offset, text },
+            BadEscape { index, text } => BadEscape { index : index + offset, text },
+            MalformedNumber { index, text } => MalformedNumber { index : index + offset, text },
+            NumberOverflow { index, text } => NumberOverflow { index : index + offset, text },
+            UnexpectedChar { index, c } => UnexpectedChar { index : index + offset, c },
+            UnexpectedEof { index } => UnexpectedEof { index : index + offset },
+            InvalidUtf8 { index } => InvalidUtf8 { index : index + offset },
+            Io { index, message } => Io { index : index + offset, message },
         }
     }
+}
 
-    Ok(ret)
+static LEXERS : [&dyn Lexer; 56] = [ &JunkLexer{}
+                                   , &DocCommentLexer{}
+                                   , &BoolLexer{}
+                                   , &NumberLexer{}
+                                   , &StringLexer{}
+                                   , &RawStringLexer{}
+                                   , &CharLexer{}
+                                   , &PunctLexer{ punct : ['('], lexeme : Lexeme::LParen }
+                                   , &PunctLexer{ punct : [')'], lexeme : Lexeme::RParen }
+                                   , &PunctLexer{ punct : ['<'], lexeme : Lexeme::LAngle }
+                                   , &PunctLexer{ punct : ['>'], lexeme : Lexeme::RAngle }
+                                   , &PunctLexer{ punct : ['{'], lexeme : Lexeme::LCurl }
+                                   , &PunctLexer{ punct : ['}'], lexeme : Lexeme::RCurl }
+                                   , &PunctLexer{ punct : ['['], lexeme : Lexeme::LSquare }
+                                   , &PunctLexer{ punct : [']'], lexeme : Lexeme::RSquare }
+                                   , &PunctLexer{ punct : ['|'], lexeme : Lexeme::OrBar }
+                                   , &PunctLexer{ punct : [';'], lexeme : Lexeme::SemiColon }
+                                   , &PunctLexer{ punct : [','], lexeme : Lexeme::Comma }
+                                   , &PunctLexer{ punct : ['='], lexeme : Lexeme::Equal }
+                                   , &PunctLexer{ punct : ['=', '>'], lexeme : Lexeme::RightDoubleArrow }
+                                   , &PunctLexer{ punct : ['-', '>'], lexeme : Lexeme::RightArrow }
+                                   , &PunctLexer{ punct : ['+'], lexeme : Lexeme::Plus }
+                                   , &PunctLexer{ punct : ['-'], lexeme : Lexeme::Minus }
+                                   , &PunctLexer{ punct : ['*'], lexeme : Lexeme::Star }
+                                   , &PunctLexer{ punct : ['/'], lexeme : Lexeme::Slash }
+                                   , &PunctLexer{ punct : ['%'], lexeme : Lexeme::Percent }
+                                   , &PunctLexer{ punct : ['=', '='], lexeme : Lexeme::DoubleEqual }
+                                   , &PunctLexer{ punct : ['!', '='], lexeme : Lexeme::NotEqual }
+                                   , &PunctLexer{ punct : ['<', '='], lexeme : Lexeme::LessEqual }
+                                   , &PunctLexer{ punct : ['>', '='], lexeme : Lexeme::GreaterEqual }
+                                   , &PunctLexer{ punct : ['&', '&'], lexeme : Lexeme::DoubleAmpersand }
+                                   , &PunctLexer{ punct : ['|', '|'], lexeme : Lexeme::DoubleOrBar }
+                                   , &PunctLexer{ punct : [':', ':'], lexeme : Lexeme::DoubleColon }
+                                   , &PunctLexer{ punct : [':'], lexeme : Lexeme::Colon }
+                                   , &PunctLexer{ punct : ['.'], lexeme : Lexeme::Dot }
+                                   , &PunctLexer{ punct : ['.', '.'], lexeme : Lexeme::DotDot }
+                                   , &PunctLexer{ punct : ['@'], lexeme : Lexeme::At }
+                                   , &PunctLexer{ punct : ['\\'], lexeme : Lexeme::Backslash }
+                                   , &PunctLexer{ punct : ['!'], lexeme : Lexeme::Bang }
+                                   , &KeywordLexer{ keyword : "fun", lexeme : Lexeme::Fun }
+                                   , &KeywordLexer{ keyword : "let", lexeme : Lexeme::Let }
+                                   , &KeywordLexer{ keyword : "spec", lexeme : Lexeme::Spec }
+                                   , &KeywordLexer{ keyword : "data", lexeme : Lexeme::Data }
+                                   , &KeywordLexer{ keyword : "match", lexeme : Lexeme::Match }
+                                   , &KeywordLexer{ keyword : "infixl", lexeme : Lexeme::Infixl }
+                                   , &KeywordLexer{ keyword : "infixr", lexeme : Lexeme::Infixr }
+                                   , &KeywordLexer{ keyword : "infix", lexeme : Lexeme::Infix }
+                                   , &KeywordLexer{ keyword : "if", lexeme : Lexeme::If }
+                                   , &KeywordLexer{ keyword : "else", lexeme : Lexeme::Else }
+                                   , &KeywordLexer{ keyword : "with", lexeme : Lexeme::With }
+                                   , &KeywordLexer{ keyword : "module", lexeme : Lexeme::Module }
+                                   , &KeywordLexer{ keyword : "import", lexeme : Lexeme::Import }
+                                   , &KeywordLexer{ keyword : "pub", lexeme : Lexeme::Pub }
+                                   , &KeywordLexer{ keyword : "_", lexeme : Lexeme::Underscore }
+                                   , &SymbolLexer{} 
+                                   // Last, so that the built in operators above win any tie.
+                                   , &OperatorLexer{}
+                                   ];
+
+pub fn lex(s : &str, symbols : &mut SymbolTable) -> Result<Vec<Token>, LexError> {
+    let (tokens, mut errors) = lex_input(s, symbols, false, None);
+
+    if errors.is_empty() {
+        Ok(tokens)
+    }
+    else {
+        Err(errors.remove(0))
+    }
 }
 
-struct Input<'a> {
-    cs : Peekable<CharIndices<'a>>
+/// Lexes all of the input, skipping ahead to the next whitespace or delimiter 
+/// after each error so that every lexical error in the input is reported.
+pub fn lex_with_recovery(s : &str, symbols : &mut SymbolTable) -> (Vec<Token>, Vec<LexError>) {
+    lex_input(s, symbols, true, None)
 }
 
-impl<'a> Input<'a> {
-    fn restore_point(&self) -> Peekable<CharIndices<'a>> {
-        self.cs.clone()
-    }
+/// Lexes the input while keeping whitespace and comments as trivia attached to the
+/// surrounding tokens so that the original source can be reproduced exactly.
+pub fn lex_lossless<'a>(s : &'a str, symbols : &mut SymbolTable) -> Result<LosslessTokens<'a>, LexError> {
+    let mut trivia = vec![];
+    let (tokens, mut errors) = lex_input(s, symbols, false, Some(&mut trivia));
 
-    fn restore(&mut self, rp : Peekable<CharIndices<'a>>) {
-        self.cs = rp;
+    if !errors.is_empty() {
+        return Err(errors.remove(0));
     }
 
-    fn next(&mut self) -> Option<(usize, char)> {
-        self.cs.next()
-    }
+    let mut trivia = trivia.into_iter().peekable();
+    let mut tokens = tokens.into_iter().peekable();
+    let mut ret = vec![];
+
+    while let Some(token) = tokens.next() {
+        let next = tokens.peek().map_or(usize::MAX, |t| t.span.start);
+
+        let mut leading = vec![];
+        while let Some(t) = trivia.next_if(|t| t.span.start < token.span.start) {
+            leading.push(t);
+        }
 
-    fn peek(&mut self) -> Option<&(usize, char)> {
-        self.cs.peek()
+        // Trailing trivia runs to the end of the token's line.  Everything from the 
+        // newline on leads into the next token.
+        let mut trailing = vec![];
+        while let Some(t) = trivia.next_if(|t| t.kind != TriviaKind::Newline && t.span.start < next) {
+            trailing.push(t);
+        }
+
+        let text = &s[token.span.start..token.span.end];
+        ret.push(LosslessToken { leading, token, text, trailing });
     }
+
+    Ok(LosslessTokens { tokens : ret, eof : trivia.collect() })
 }
 
-trait Lexer {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool;
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize>;
+const READ_CHUNK : usize = 8192;
+
+/// Lexes tokens out of a reader as they are asked for.  Only the source from the start
+/// of the current token onward is kept in memory.
+pub fn lex_reader<R : Read>(reader : R, symbols : &mut SymbolTable) -> TokenStream<'_, R> {
+    TokenStream { reader
+                , symbols
+                , buffer : String::new()
+                , undecoded : vec![]
+                , offset : 0
+                , index : 0
+                , position : Position::new()
+                , after_operand : false
+                , eof : false
+                , error : None
+                , done : false
+                }
 }
 
-struct JunkLexer {}
+pub struct TokenStream<'a, R : Read> {
+    reader : R,
+    symbols : &'a mut SymbolTable,
+    buffer : String,
+    // Bytes at the end of the last read that don't make up a whole character yet.
+    undecoded : Vec<u8>,
+    // Offset of the start of the buffer in the whole input.
+    offset : usize,
+    index : usize,
+    position : Position,
+    after_operand : bool,
+    eof : bool,
+    // Read and decoding errors are held until every token before them has been lexed.
+    error : Option<LexError>,
+    done : bool,
+}
 
-impl Lexer for JunkLexer {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        match input.peek() {
-            Some((_, c)) if c.is_whitespace() => return true,
-            Some((_, '/'))=> { },
-            _ => return false,
-        }
+impl<'a, R : Read> TokenStream<'a, R> {
+    // A token that runs off the end of the buffer is lexed again from its start after 
+    // the refill.  Reading at least as much again as is already pending means that a 
+    // huge comment or string gets rescanned a logarithmic number of times rather than 
+    // once per chunk.  With nothing pending a single read is enough, so interactive 
+    // input isn't held up.
+    fn fill(&mut self) {
+        self.compact();
 
-        let rp = input.restore_point();
+        let pending = self.buffer.len();
+        let mut chunk = [0u8; READ_CHUNK];
 
-        input.next();
+        loop {
+            let len = loop {
+                match self.reader.read(&mut chunk) {
+                    Ok(len) => break len,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => { },
+                    Err(e) => {
+                        self.error = Some(LexError::Io { index : self.offset + self.buffer.len(), message : e.to_string() });
+                        self.eof = true;
+                        return;
+                    },
+                }
+            };
 
-        match input.next() {
-            Some((_, '*')) => { input.restore(rp); true },
-            _ => { input.restore(rp); false },
+            if len == 0 {
+                if !self.undecoded.is_empty() {
+                    self.error = Some(LexError::InvalidUtf8 { index : self.offset + self.buffer.len() });
+                }
+                self.eof = true;
+                return;
+            }
+
+            self.undecoded.extend_from_slice(&chunk[..len]);
+
+            let valid = match std::str::from_utf8(&self.undecoded) {
+                Ok(text) => text.len(),
+                Err(e) => {
+                    if e.error_len().is_some() {
+                        self.error = Some(LexError::InvalidUtf8 { index : self.offset + self.buffer.len() + e.valid_up_to() });
+                        self.eof = true;
+                    }
+                    e.valid_up_to()
+                },
+            };
+
+            // The prefix was just checked so this can't fail.
+            self.buffer.push_str(std::str::from_utf8(&self.undecoded[..valid]).unwrap());
+            self.undecoded.drain(..valid);
+
+            if self.eof || self.buffer.len() - pending >= pending {
+                return;
+            }
         }
     }
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let mut comment = 0;
+    // Drops everything before the token that is about to be lexed.  The position is 
+    // walked up to it first, so spans carry on from the right line and column.
+    fn compact(&mut self) {
+        if self.index > 0 {
+            self.position.span(&self.buffer, self.index, self.index);
+            self.buffer.drain(..self.index);
+            self.offset += self.index;
+            self.position.index -= self.index;
+            self.index = 0;
+        }
+    }
+}
 
-        loop {
-            if comment > 0 {
-                match input.next() {
-                    Some((_, '*')) => {
-                        match input.next() {
-                            Some((_, '/')) => { comment-=1; },
-                            Some((_, _)) => { },
-                            None => return Err(0), // TODO end of file
-                        }
-                    },
-                    Some((_, '/')) => {
-                        match input.next() {
-                            Some((_, '*')) => { comment+=1; },
-                            Some((_, _)) => { },
-                            None => return Err(0), // TODO end of file
-                        }
-                    }
-                    Some((_, _)) => { },
-                    None => return Err(0),  // TODO end of file
+impl<'a, R : Read> Iterator for TokenStream<'a, R> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if self.index == self.buffer.len() {
+                if !self.eof {
+                    self.fill();
+                    continue;
                 }
+                self.done = true;
+                return self.error.take().map(Err);
             }
-            else {
-                match input.peek() {
-                    Some((_, c)) if c.is_whitespace() => { input.next(); },
-                    Some((_, '/')) => {
-                        let rp = input.restore_point();
-                        input.next();
-                        match input.peek() {
-                            Some((_, '*')) => { input.next(); comment+=1 },
-                            _ => { input.restore(rp); return Ok(Lexeme::Junk); },
-                        }
-                    },
-                    _ => return Ok(Lexeme::Junk),
-                }
+
+            let mut input = Input { src : &self.buffer
+                                  , index : self.index
+                                  , symbols : self.symbols
+                                  , position : self.position
+                                  , after_operand : self.after_operand
+                                  , at_end : false
+                                  };
+
+            let start = input.index();
+            // The tokens inside an interpolated string get their spans before the string 
+            // does.  Coming back to the string's start from here, rather than walking 
+            // backwards, means the line before it doesn't have to be kept around.
+            input.span(start, start);
+            let at_start = input.position;
+
+            let result = lex_token(&mut input);
+
+            // Anything that looked at the end of the buffer might come out differently 
+            // once more of the input is available.
+            if input.at_end && !self.eof {
+                self.fill();
+                continue;
+            }
+            if input.at_end && self.error.is_some() {
+                self.done = true;
+                return self.error.take().map(Err);
+            }
+
+            match result {
+                Ok(Lexeme::Junk) => {
+                    self.index = input.index();
+                },
+                Ok(lexeme) => {
+                    let end = input.index();
+                    input.position = at_start;
+                    let span = input.span(start, end);
+                    self.position = input.position;
+                    self.index = end;
+                    self.after_operand = ends_operand(&lexeme);
+
+                    let offset = self.offset;
+                    let token = move_token(&Token { lexeme, span }, &|s : &Span| Span { start : s.start + offset, end : s.end + offset, ..*s });
+                    return Some(Ok(token));
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.moved(self.offset)));
+                },
             }
         }
+
+        None
     }
 }
 
-struct SymbolLexer {}
+#[derive(PartialEq, Debug, Clone)]
+pub struct Edit {
+    pub start : usize,
+    pub end : usize,
+    pub text : String,
+}
 
-impl Lexer for SymbolLexer {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        match input.peek() {
-            Some((_, c)) => c.is_alphabetic() || *c == '_',
-            None => false,
+/// Re-tokenizes source that has had a single edit applied to it.  Tokens before the 
+/// edit are reused as is, and tokens after it are reused (with shifted spans) as soon 
+/// as lexing the new source lines back up with the old tokens.
+pub fn relex(old : &[Token], edit : &Edit, s : &str, symbols : &mut SymbolTable) -> Result<Vec<Token>, LexError> {
+    // Lexing always restarts at the beginning of a token because that is the only place 
+    // where the lexer can't be inside of a comment or string.  The last token before the
+    // edit is relexed as well because lexers look a character or two past the end of 
+    // what they consume (ie '1.' vs '1.5').
+    let restart = old.iter().rposition(|t| t.span.end < edit.start).unwrap_or(0);
+    let delta = edit.text.len() as isize - (edit.end - edit.start) as isize;
+    let edit_end = edit.start + edit.text.len();
+
+    let mut tokens = old[..restart].to_vec();
+    let mut input = Input::new(s, symbols);
+
+    if let Some(t) = old.get(restart) {
+        if t.span.start <= edit.start {
+            input.index = t.span.start;
+            input.position = Position { index : t.span.start, line : t.span.line, column : t.span.column };
+            input.after_operand = restart > 0 && ends_operand(&old[restart - 1].lexeme);
         }
     }
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let mut letters = vec![];
+    let shift = |index : usize| (index as isize + delta) as usize;
+    let mut cursor = old.iter().position(|t| t.span.start >= edit.end).unwrap_or(old.len());
 
-        let mut rp = input.restore_point();
-        let mut v = input.next();
+    while input.peek().is_some() {
+        let start = input.index();
+        let lexeme = lex_token(&mut input)?;
 
-        match v {
-            Some((_, v)) if v.is_alphabetic() || v == '_' => letters.push(v),
-            Some((index, _)) => { input.restore(rp); return Err(index) },
-            _ => return Err(0), // TODO get index? (end of input)
+        if lexeme == Lexeme::Junk {
+            continue;
         }
 
-        rp = input.restore_point();
-        v = input.next();
+        let end = input.index();
+        let span = input.span(start, end);
+        input.after_operand = ends_operand(&lexeme);
 
-        loop {
-            match v {
-                Some((_, v)) if v.is_alphanumeric() || v == '_' => letters.push(v),
-                Some((_, _)) => { input.restore(rp); break },
-                _ => break, 
+        if start >= edit_end {
+            while cursor < old.len() && shift(old[cursor].span.start) < start {
+                cursor += 1;
             }
-            rp = input.restore_point();
-            v = input.next();
-        }
 
-        if letters[0].is_uppercase() {
-            Ok(Lexeme::UpperCaseSymbol(letters.into_iter().collect::<String>()))
-        }
-        else {
-            Ok(Lexeme::LowerCaseSymbol(letters.into_iter().collect::<String>()))
+            if cursor < old.len() 
+                && shift(old[cursor].span.start) == start 
+                && shift(old[cursor].span.end) == end 
+                && old[cursor].lexeme == lexeme {
+
+                let line_delta = span.line as isize - old[cursor].span.line as isize;
+                let column_delta = span.column as isize - old[cursor].span.column as isize;
+                let resync_line = old[cursor].span.line;
+
+                // Only tokens on the same line as the resync point have their column moved.
+                let move_span = |old : &Span| {
+                    let column = if old.line == resync_line { 
+                        (old.column as isize + column_delta) as usize 
+                    } 
+                    else { 
+                        old.column 
+                    };
+
+                    Span { start : shift(old.start)
+                         , end : shift(old.end)
+                         , line : (old.line as isize + line_delta) as usize
+                         , column 
+                         }
+                };
+
+                tokens.push(Token { lexeme, span });
+                tokens.extend(old[cursor + 1..].iter().map(|t| move_token(t, &move_span)));
+
+                return Ok(tokens);
+            }
         }
+
+        tokens.push(Token { lexeme, span });
     }
+
+    Ok(tokens)
 }
 
-struct BoolLexer {}
+fn move_token(token : &Token, move_span : &dyn Fn(&Span) -> Span) -> Token {
+    let lexeme = match &token.lexeme {
+        Lexeme::InterpolatedString(parts) => {
+            let parts = parts.iter().map(|part| match part {
+                StringPart::Code(tokens) => StringPart::Code(tokens.iter().map(|t| move_token(t, move_span)).collect()),
+                StringPart::Literal(l) => StringPart::Literal(l.clone()),
+            }).collect();
+            Lexeme::InterpolatedString(parts)
+        },
+        lexeme => lexeme.clone(),
+    };
+
+    Token { lexeme, span : move_span(&token.span) }
+}
 
-impl Lexer for BoolLexer {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        match input.peek() {
-            Some((_, c)) => *c == 't' || *c == 'f',
-            None => false,
-        }
-    }
+fn lex_input<'a>(s : &'a str, symbols : &mut SymbolTable, recover : bool, mut trivia : Option<&mut Vec<Trivia<'a>>>) -> (Vec<Token>, Vec<LexError>) {
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let symbol_lexer = SymbolLexer {};
+    let mut input = Input::new(s, symbols);
 
-        let rp = input.restore_point();
+    let mut tokens = vec![];
+    let mut errors = vec![];
 
-        match symbol_lexer.lex(input) {
-            Ok(Lexeme::LowerCaseSymbol(lexeme)) if lexeme == "true" => Ok(Lexeme::Bool(true)),
-            Ok(Lexeme::LowerCaseSymbol(lexeme)) if lexeme == "false" => Ok(Lexeme::Bool(false)),
-            _ => { input.restore(rp); Err(0) }, // TODO need a way to grab index
+    while input.peek().is_some() {
+        let start = input.index();
+        match lex_token(&mut input) {
+            Ok(Lexeme::Junk) => {
+                if let Some(trivia) = trivia.as_mut() {
+                    let end = input.index();
+                    split_trivia(&mut input, s, start, end, trivia);
+                }
+            },
+            Ok(lexeme) => {
+                let end = input.index();
+                let span = input.span(start, end);
+                input.after_operand = ends_operand(&lexeme);
+                tokens.push(Token { lexeme, span });
+            },
+            Err(e) => {
+                errors.push(e);
+                if !recover {
+                    break;
+                }
+                skip_to_boundary(&mut input, start);
+            },
         }
     }
+
+    (tokens, errors)
 }
 
-struct NumberLexer {}
+// Breaks up a run of junk that has already been lexed into individual pieces of 
+// whitespace, newlines and comments.  The source is passed in separately so that the 
+// trivia can borrow from it for longer than the input lives.
+fn split_trivia<'s>(input : &mut Input, src : &'s str, start : usize, end : usize, trivia : &mut Vec<Trivia<'s>>) {
+    let mut index = start;
 
-impl Lexer for NumberLexer {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        match input.peek() {
-            Some((_, c)) => c.is_digit(10) || *c == '-',
-            None => false,
+    while index < end {
+        let rest = &src[index..end];
+
+        let (kind, len) = if rest.starts_with('\n') {
+            (TriviaKind::Newline, 1)
+        }
+        else if rest.starts_with("\r\n") {
+            (TriviaKind::Newline, 2)
         }
+        else if rest.starts_with("//") {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            let len = if rest[..len].ends_with('\r') { len - 1 } else { len };
+            (TriviaKind::LineComment, len)
+        }
+        else if rest.starts_with("/*") {
+            let mut depth = 0;
+            let mut len = 0;
+            while len < rest.len() {
+                if rest[len..].starts_with("/*") {
+                    depth += 1;
+                    len += 2;
+                }
+                else if rest[len..].starts_with("*/") {
+                    depth -= 1;
+                    len += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                else {
+                    len += rest[len..].chars().next().map_or(1, char::len_utf8);
+                }
+            }
+            (TriviaKind::BlockComment, len)
+        }
+        else {
+            let len = rest.find(|c : char| !c.is_whitespace() || c == '\n' || c == '\r').unwrap_or(rest.len());
+            // A lone '\r' that isn't part of a newline is still just whitespace.
+            let len = if len == 0 { 1 } else { len };
+            (TriviaKind::Whitespace, len)
+        };
+
+        let span = input.span(index, index + len);
+        trivia.push(Trivia { kind, span, text : &rest[..len] });
+        index += len;
     }
+}
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let mut digits = vec![];
-        let mut has_decimal = false;
+// A '-' directly after an operand is subtraction rather than the start of a negative
+// number, so that 'a-1' lexes the same as 'a - 1'.
+fn ends_operand(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::LowerCaseSymbol(_) 
+                    | Lexeme::UpperCaseSymbol(_) 
+                    | Lexeme::Bool(_) 
+                    | Lexeme::Integer(_) 
+                    | Lexeme::Decimal(_) 
+                    | Lexeme::String(_) 
+                    | Lexeme::InterpolatedString(_) 
+                    | Lexeme::Char(_) 
+                    | Lexeme::RParen
+                    | Lexeme::RCurl
+                    | Lexeme::RSquare )
+}
 
-        match input.peek() {
-            Some((_, '-')) => { input.next(); digits.push('-') },
-            _ => { },
+// For every ascii character, the set of lexers in LEXERS that can start with it.  
+// Anything else has to go through every lexer.
+fn dispatch(c : char) -> u128 {
+    static DISPATCH : OnceLock<[u128; 128]> = OnceLock::new();
+
+    let table = DISPATCH.get_or_init(|| {
+        let mut table = [0; 128];
+        for (c, set) in table.iter_mut().enumerate() {
+            for (i, lexer) in LEXERS.iter().enumerate() {
+                if lexer.starts_with(c as u8 as char) {
+                    *set |= 1 << i;
+                }
+            }
         }
+        table
+    });
 
-        let mut rp = input.restore_point();
-        let mut v = input.next();
+    if c.is_ascii() {
+        table[c as usize]
+    }
+    else {
+        u128::MAX >> (128 - LEXERS.len())
+    }
+}
 
-        loop {
-            match v {
-                Some((_, v)) if v.is_digit(10) => digits.push(v),
-                Some((_, '.')) if has_decimal => { 
-                    match input.peek() {
-                        Some((index, v)) if v.is_digit(10) => { return Err(*index); },
-                        _ => { input.restore(rp); break },
+// Every usable lexer is tried from the same starting point and the one that consumes 
+// the most input wins.  Ties go to whichever lexer comes first in the table, which is 
+// how keywords and bools beat out the symbol lexer when they read the same identifier.
+fn lex_token<'a>(input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+    let start = input.restore_point();
+    let mut longest : Option<(Lexeme, usize)> = None;
+    let mut failure = None;
+
+    let mut candidates = match input.peek() {
+        Some((_, c)) => dispatch(c),
+        None => 0,
+    };
+
+    while candidates != 0 {
+        let lexer = LEXERS[candidates.trailing_zeros() as usize];
+        candidates &= candidates - 1;
+
+        if lexer.usable(input) {
+            match lexer.lex(input) {
+                Ok(lexeme) => {
+                    let end = input.index();
+                    match longest {
+                        Some((_, longest_end)) if longest_end >= end => { },
+                        _ => longest = Some((lexeme, end)),
                     }
-                }, 
-                Some((_, '.')) => { 
-                    match input.peek() {
-                        Some((_, v)) if v.is_digit(10) => { },
-                        _ => { input.restore(rp); break },
+                },
+                Err(e) => {
+                    let stop = input.restore_point();
+                    match failure {
+                        Some((_, failure_stop)) if failure_stop >= stop => { },
+                        _ => failure = Some((e, stop)),
                     }
-
-                    has_decimal = true;
-                    digits.push('.');
                 },
-                Some((_, _)) => { input.restore(rp); break},
-                _ => break,
             }
-            rp = input.restore_point();
-            v = input.next();
+            input.restore(start);
         }
+    }
 
-        if has_decimal {
-            let res = digits.into_iter().collect::<String>().parse::<f64>().expect("parse::<f64>() failure");
-            Ok(Lexeme::Decimal(res))
+    // On failure leave the input wherever the failing lexer gave up so that recovery 
+    // does not have to rescan something like the body of an unterminated string.  A 
+    // lexer that got further than the longest match before failing had already matched 
+    // its opener, like the '/*' of a comment or the 'r#"' of a raw string, so its error 
+    // wins over the '/' or 'r' that some other lexer could make of the start.
+    match (longest, failure) {
+        (Some((_, end)), Some((e, stop))) if stop > end => { input.restore(stop); Err(e) },
+        (Some((lexeme, stop)), _) => { input.restore(stop); Ok(lexeme) },
+        (None, Some((e, stop))) => { input.restore(stop); Err(e) },
+        (None, None) => Err(input.unexpected()),
+    }
+}
+
+// Lexes the code inside of a '${' ... '}' string interpolation up to and including the
+// closing '}'.  Nested braces and strings are handled by the normal lexers.
+fn lex_interpolation<'a>(input : &mut Input<'a>) -> Result<Vec<Token>, LexError> {
+    let mut tokens = vec![];
+    let mut depth = 0;
+
+    input.after_operand = false;
+
+    loop {
+        if input.peek().is_none() {
+            return Err(input.unexpected());
+        }
+
+        let start = input.index();
+        let lexeme = lex_token(input)?;
+
+        match lexeme {
+            Lexeme::Junk => continue,
+            Lexeme::LCurl => depth += 1,
+            Lexeme::RCurl if depth == 0 => return Ok(tokens),
+            Lexeme::RCurl => depth -= 1,
+            _ => { },
+        }
+
+        let end = input.index();
+        let span = input.span(start, end);
+        input.after_operand = ends_operand(&lexeme);
+        tokens.push(Token { lexeme, span });
+    }
+}
+
+fn skip_to_boundary<'a>(input : &mut Input<'a>, start : usize) {
+    if input.index() == start {
+        input.next();
+    }
+
+    while let Some((_, c)) = input.peek() {
+        if c.is_whitespace() || matches!(c, '(' | ')' | '{' | '}' | ';' | ',') {
+            break;
+        }
+        input.next();
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    index : usize,
+    line : usize,
+    column : usize,
+}
+
+impl Position {
+    fn new() -> Self {
+        Position { index : 0, line : 1, column : 1 }
+    }
+
+    // Spans are almost always handed out in source order, so line and column only need 
+    // to walk forward from wherever the previous span left off.  The exception is a 
+    // string containing interpolations, which gets its span after the tokens inside it.
+    fn span(&mut self, s : &str, start : usize, end : usize) -> Span {
+        if start >= self.index {
+            for c in s[self.index..start].chars() {
+                if c == '\n' {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                else {
+                    self.column += 1;
+                }
+            }
         }
         else {
-            let res = digits.into_iter().collect::<String>().parse::<i64>().expect("parse::<i64>() failure");
-            Ok(Lexeme::Integer(res))
+            self.line -= s[start..self.index].matches('\n').count();
+            let line_start = s[..start].rfind('\n').map_or(0, |i| i + 1);
+            self.column = s[line_start..start].chars().count() + 1;
         }
+        self.index = start;
+
+        Span { start, end, line : self.line, column : self.column }
     }
 }
 
+// The input is walked by byte offset into the source so that restore points are just
+// an index and tokens can be sliced straight out of the source.  Only non-ascii 
+// characters need to be decoded.
+struct Input<'a> {
+    src : &'a str,
+    index : usize,
+    symbols : &'a mut SymbolTable,
+    position : Position,
+    after_operand : bool,
+    // Set whenever a lexer looks past the end of the source.
+    at_end : bool,
+}
 
-struct StringLexer {}
+impl<'a> Input<'a> {
+    fn new(s : &'a str, symbols : &'a mut SymbolTable) -> Self {
+        Input { src : s, index : 0, symbols, position : Position::new(), after_operand : false, at_end : false }
+    }
 
-impl Lexer for StringLexer {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        match input.peek() {
-            Some((_, c)) => *c == '"',
-            None => false,
+    fn span(&mut self, start : usize, end : usize) -> Span {
+        self.position.span(self.src, start, end)
+    }
+
+    fn index(&mut self) -> usize {
+        self.index
+    }
+
+    fn text_from(&mut self, start : usize) -> String {
+        self.src[start..self.index].to_string()
+    }
+
+    fn unexpected(&mut self) -> LexError {
+        match self.peek() {
+            Some((index, c)) => LexError::UnexpectedChar { index, c },
+            None => LexError::UnexpectedEof { index : self.src.len() },
         }
     }
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let mut cs = vec![];
+    fn restore_point(&self) -> usize {
+        self.index
+    }
 
-        let rp = input.restore_point();
-        let mut v = input.next();
+    fn restore(&mut self, rp : usize) {
+        self.index = rp;
+    }
 
-        match v {
-            Some((_, '"')) => { },
-            _ => { input.restore(rp); return Err(0); }, // TODO index   
+    fn next(&mut self) -> Option<(usize, char)> {
+        let r = self.peek();
+        if let Some((index, c)) = r {
+            self.index = index + c.len_utf8();
         }
+        r
+    }
 
-        v = input.next();
+    fn peek(&mut self) -> Option<(usize, char)> {
+        match self.src.as_bytes().get(self.index) {
+            Some(b) if b.is_ascii() => Some((self.index, *b as char)),
+            Some(_) => self.src[self.index..].chars().next().map(|c| (self.index, c)),
+            None => { self.at_end = true; None },
+        }
+    }
+}
+
+trait Lexer : Sync {
+    fn starts_with(&self, c : char) -> bool;
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool;
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError>;
+}
+
+#[derive(PartialEq)]
+enum Comment {
+    Line,
+    Block,
+    DocLine,
+    DocBlock,
+}
+
+// Looks at what kind of comment (if any) starts at the current input without
+// consuming anything.  Like rust, '////' and '/***' are plain comments and '/**/' is
+// an empty block comment rather than the start of a doc comment.
+fn comment_start<'a>(input : &mut Input<'a>) -> Option<Comment> {
+    let rp = input.restore_point();
+    let mut cs = ['\0'; 4];
+    for c in cs.iter_mut() {
+        if let Some((_, x)) = input.next() {
+            *c = x;
+        }
+    }
+    input.restore(rp);
+
+    match cs {
+        ['/', '/', '/', '/'] => Some(Comment::Line),
+        ['/', '/', '/', _] => Some(Comment::DocLine),
+        ['/', '/', _, _] => Some(Comment::Line),
+        ['/', '*', '*', '/'] => Some(Comment::Block),
+        ['/', '*', '*', '*'] => Some(Comment::Block),
+        ['/', '*', '*', _] => Some(Comment::DocBlock),
+        ['/', '*', _, _] => Some(Comment::Block),
+        _ => None,
+    }
+}
+
+// Consumes the rest of a (possibly nested) block comment whose opening '/*' has 
+// already been consumed.
+fn block_comment<'a>(input : &mut Input<'a>, start : usize) -> Result<(), LexError> {
+    let mut comment = 1;
+
+    while comment > 0 {
+        match input.next() {
+            Some((_, '*')) => {
+                if let Some((_, '/')) = input.peek() {
+                    input.next();
+                    comment-=1;
+                }
+            },
+            Some((_, '/')) => {
+                if let Some((_, '*')) = input.peek() {
+                    input.next();
+                    comment+=1;
+                }
+            },
+            Some((_, _)) => { },
+            None => return Err(LexError::UnterminatedComment { start, text : input.text_from(start) }),
+        }
+    }
+
+    Ok(())
+}
+
+struct JunkLexer {}
+
+impl Lexer for JunkLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c.is_whitespace() || c == '/'
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        match input.peek() {
+            Some((_, c)) if c.is_whitespace() => true,
+            Some((_, '/')) => matches!( comment_start(input), Some(Comment::Line) | Some(Comment::Block) ),
+            _ => false,
+        }
+    }
 
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
         loop {
-            match v {
-                Some((_, '"')) => break,
-                Some((_, '\\')) => {
-                    let escape = input.next();
-                    match escape {
-                        Some((_, 't')) => cs.push('\t'),
-                        Some((_, 'n')) => cs.push('\n'),
-                        Some((_, 'r')) => cs.push('\r'),
-                        Some((_, '\\')) => cs.push('\\'),
-                        Some((_, '"')) => cs.push('"'),
-                        Some((index, _)) => return Err(index),
-                        None => return Err(0), // TODO end of file
+            match input.peek() {
+                Some((_, c)) if c.is_whitespace() => { input.next(); },
+                Some((_, '/')) => {
+                    let start = input.index();
+                    match comment_start(input) {
+                        Some(Comment::Line) => {
+                            while let Some((_, c)) = input.peek() {
+                                if c == '\n' {
+                                    break;
+                                }
+                                input.next();
+                            }
+                        },
+                        Some(Comment::Block) => {
+                            input.next();
+                            input.next();
+                            block_comment(input, start)?;
+                        },
+                        _ => return Ok(Lexeme::Junk),
                     }
                 },
-                Some((_, v)) => cs.push(v),
-                None => return Err(0), // TODO end of file
+                _ => return Ok(Lexeme::Junk),
             }
-            v = input.next();
         }
-
-        Ok(Lexeme::String(cs.into_iter().collect::<String>()))
     }
 }
 
-struct KeywordLexer {
-    keyword : &'static str,
-    lexeme : Lexeme,
-}
+struct DocCommentLexer {}
+
+impl Lexer for DocCommentLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c == '/'
+    }
 
-impl Lexer for KeywordLexer {
     fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        match self.lex(input) {
-            Ok(_) => true,
-            Err(_) => false,
+        matches!( comment_start(input), Some(Comment::DocLine) | Some(Comment::DocBlock) )
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let start = input.index();
+
+        match comment_start(input) {
+            Some(Comment::DocLine) => {
+                input.next();
+                input.next();
+                input.next();
+
+                while let Some((_, c)) = input.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    input.next();
+                }
+
+                let text = input.text_from(start + 3);
+                Ok(Lexeme::DocComment(text.trim_end_matches('\r').to_string()))
+            },
+            Some(Comment::DocBlock) => {
+                input.next();
+                input.next();
+                input.next();
+
+                block_comment(input, start)?;
+
+                let text = input.text_from(start + 3);
+                Ok(Lexeme::DocComment(text[..text.len() - 2].to_string()))
+            },
+            _ => Err(input.unexpected()),
         }
     }
+}
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let symbol_lexer = SymbolLexer {};
+struct SymbolLexer {}
 
-        let rp = input.restore_point();
+impl Lexer for SymbolLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c.is_alphabetic() || c == '_'
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        match input.peek() {
+            Some((_, c)) => c.is_alphabetic() || c == '_',
+            None => false,
+        }
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let name = identifier(input)?;
+        let symbol = input.symbols.intern(name);
+
+        if name.starts_with(char::is_uppercase) {
+            Ok(Lexeme::UpperCaseSymbol(symbol))
+        }
+        else {
+            Ok(Lexeme::LowerCaseSymbol(symbol))
+        }
+    }
+}
+
+// The characters that user defined operators are made of.
+fn is_operator_char(c : char) -> bool {
+    matches!( c, '!' | '$' | '%' | '&' | '*' | '+' | '-' | '/' | ':' | '<' | '=' | '>' | '?' | '^' | '|' | '~' )
+}
+
+struct OperatorLexer {}
+
+impl Lexer for OperatorLexer {
+    fn starts_with(&self, c : char) -> bool {
+        is_operator_char(c)
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        match input.peek() {
+            Some((_, c)) => is_operator_char(c) && comment_start(input).is_none(),
+            None => false,
+        }
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let start = input.index();
+
+        // 'a +// b' is '+' followed by a comment, not a '+//' operator.
+        while let Some((_, c)) = input.peek() {
+            if !is_operator_char(c) || comment_start(input).is_some() {
+                break;
+            }
+            input.next();
+        }
+
+        if input.index() == start {
+            return Err(input.unexpected());
+        }
+
+        let name = input.text_from(start);
+        Ok(Lexeme::Operator(input.symbols.intern(&name)))
+    }
+}
+
+fn identifier<'a>(input : &mut Input<'a>) -> Result<&'a str, LexError> {
+    let start = input.index();
+
+    match input.peek() {
+        Some((_, c)) if c.is_alphabetic() || c == '_' => { input.next(); },
+        _ => return Err(input.unexpected()),
+    }
+
+    while let Some((_, c)) = input.peek() {
+        if !(c.is_alphanumeric() || c == '_') {
+            break;
+        }
+        input.next();
+    }
+
+    let end = input.index();
+    Ok(&input.src[start..end])
+}
+
+struct BoolLexer {}
+
+impl Lexer for BoolLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c == 't' || c == 'f'
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        match input.peek() {
+            Some((_, c)) => c == 't' || c == 'f',
+            None => false,
+        }
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let rp = input.restore_point();
+
+        match identifier(input) {
+            Ok("true") => Ok(Lexeme::Bool(true)),
+            Ok("false") => Ok(Lexeme::Bool(false)),
+            _ => { input.restore(rp); Err(input.unexpected()) },
+        }
+    }
+}
+
+struct NumberLexer {}
+
+impl Lexer for NumberLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c.is_ascii_digit() || c == '-'
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        let after_operand = input.after_operand;
+        match input.peek() {
+            Some((_, c)) => c.is_ascii_digit() || (c == '-' && !after_operand),
+            None => false,
+        }
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let mut has_digit = false;
+        let mut has_decimal = false;
+        let mut has_exponent = false;
+        let start = input.index();
+
+        let negative = matches!( input.peek(), Some((_, '-')) );
+        if negative {
+            input.next();
+        }
+
+        if let Some(radix) = radix_prefix(input) {
+            input.next();
+            input.next();
+
+            let digits_start = input.index();
+            while let Some((_, c)) = input.peek() {
+                if c.is_digit(radix) {
+                    has_digit = true;
+                }
+                else if c != '_' {
+                    break;
+                }
+                input.next();
+            }
+
+            if !has_digit {
+                return Err(LexError::MalformedNumber { index : start, text : input.text_from(start) });
+            }
+
+            number_end(input, start, digits_start, radix)?;
+
+            let digits = &input.src[digits_start..input.index()];
+            return match integer(digits, radix, negative) {
+                Some(res) => Ok(Lexeme::Integer(res)),
+                None => Err(LexError::NumberOverflow { index : start, text : input.text_from(start) }),
+            };
+        }
+
+        let digits_start = input.index();
+        let mut last_digit = false;
+        let mut rp = input.restore_point();
+        let mut v = input.next();
+
+        loop {
+            match v {
+                Some((_, v)) if v.is_ascii_digit() => { has_digit = true; last_digit = true; },
+                Some((_, '_')) if last_digit => { },
+                Some((_, '.')) if has_decimal => { 
+                    match input.peek() {
+                        Some((_, v)) if v.is_ascii_digit() => {
+                            return Err(LexError::MalformedNumber { index : start, text : input.text_from(start) });
+                        },
+                        _ => { input.restore(rp); break },
+                    }
+                }, 
+                Some((_, '.')) => { 
+                    match input.peek() {
+                        Some((_, v)) if v.is_ascii_digit() => { },
+                        _ => { input.restore(rp); break },
+                    }
+
+                    has_decimal = true;
+                    last_digit = false;
+                },
+                Some((_, _)) => { input.restore(rp); break},
+                _ => break,
+            }
+            rp = input.restore_point();
+            v = input.next();
+        }
+
+        if !has_digit {
+            return Err(LexError::MalformedNumber { index : start, text : input.text_from(start) });
+        }
+
+        // An 'e' that isn't followed by an exponent is left for number_end to report.
+        if let Some((_, 'e')) | Some((_, 'E')) = input.peek() {
+            let rp = input.restore_point();
+            input.next();
+
+            if let Some((_, '-')) | Some((_, '+')) = input.peek() {
+                input.next();
+            }
+
+            let mut last_digit = false;
+            while let Some((_, c)) = input.peek() {
+                if c.is_ascii_digit() {
+                    last_digit = true;
+                }
+                else if c != '_' || !last_digit {
+                    break;
+                }
+                input.next();
+            }
+
+            if last_digit {
+                has_exponent = true;
+            }
+            else {
+                input.restore(rp);
+            }
+        }
+
+        number_end(input, start, digits_start, 10)?;
+
+        if has_decimal || has_exponent {
+            let text = &input.src[start..input.index()];
+            let res = if text.contains('_') {
+                text.replace('_', "").parse::<f64>()
+            }
+            else {
+                text.parse::<f64>()
+            };
+            let res = res.expect("parse::<f64>() failure");
+            if res.is_infinite() {
+                return Err(LexError::NumberOverflow { index : start, text : input.text_from(start) });
+            }
+            Ok(Lexeme::Decimal(res))
+        }
+        else {
+            let digits = &input.src[digits_start..input.index()];
+            match integer(digits, 10, negative) {
+                Some(res) => Ok(Lexeme::Integer(res)),
+                None => Err(LexError::NumberOverflow { index : start, text : input.text_from(start) }),
+            }
+        }
+    }
+}
+
+// A number has to end at something that can't continue it, so '0b1012' and '0xFFg' are
+// errors rather than a number followed by another token.  Separators have to be 
+// followed by a digit, so '1_' and '1_.5' are errors too.
+fn number_end<'a>(input : &mut Input<'a>, start : usize, digits_start : usize, radix : u32) -> Result<(), LexError> {
+    let digits = &input.src[digits_start..input.index()];
+    let mut chars = digits.chars().peekable();
+    let mut dangling_separator = false;
+    while let Some(c) = chars.next() {
+        if c == '_' && !matches!( chars.peek(), Some(&n) if n == '_' || n.is_digit(radix) ) {
+            dangling_separator = true;
+        }
+    }
+
+    let runs_on = matches!( input.peek(), Some((_, c)) if c.is_alphanumeric() || c == '_' );
+
+    if dangling_separator || runs_on {
+        while let Some((_, c)) = input.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+            input.next();
+        }
+        return Err(LexError::MalformedNumber { index : start, text : input.text_from(start) });
+    }
+
+    Ok(())
+}
+
+// Negative numbers are accumulated downwards so that i64::MIN does not overflow.
+fn integer(digits : &str, radix : u32, negative : bool) -> Option<i64> {
+    let mut res : i64 = 0;
+
+    for c in digits.chars() {
+        if let Some(d) = c.to_digit(radix) {
+            res = res.checked_mul(radix as i64)?;
+            res = if negative { res.checked_sub(d as i64)? } else { res.checked_add(d as i64)? };
+        }
+    }
+
+    Some(res)
+}
+
+fn radix_prefix<'a>(input : &mut Input<'a>) -> Option<u32> {
+    let rp = input.restore_point();
+
+    let radix = match (input.next(), input.next()) {
+        (Some((_, '0')), Some((_, 'x'))) => Some(16),
+        (Some((_, '0')), Some((_, 'o'))) => Some(8),
+        (Some((_, '0')), Some((_, 'b'))) => Some(2),
+        _ => None,
+    };
+
+    input.restore(rp);
+    radix
+}
+
+struct StringLexer {}
+
+impl Lexer for StringLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c == '"'
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        match input.peek() {
+            Some((_, c)) => c == '"',
+            None => false,
+        }
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let mut cs = String::new();
+        let mut parts = vec![];
+
+        let start = input.index();
+        let rp = input.restore_point();
+        let mut v = input.next();
+
+        match v {
+            Some((_, '"')) => { },
+            _ => { input.restore(rp); return Err(input.unexpected()); },
+        }
+
+        v = input.next();
+
+        loop {
+            match v {
+                Some((_, '"')) => break,
+                Some((_, '$')) if matches!( input.peek(), Some((_, '{')) ) => {
+                    input.next();
+
+                    let after_operand = input.after_operand;
+                    let code = lex_interpolation(input);
+                    input.after_operand = after_operand;
+
+                    match code {
+                        Ok(tokens) => {
+                            if !cs.is_empty() {
+                                parts.push(StringPart::Literal(std::mem::take(&mut cs)));
+                            }
+                            parts.push(StringPart::Code(tokens));
+                        },
+                        Err(LexError::UnexpectedEof { .. }) => return Err(LexError::UnterminatedString { start, text : input.text_from(start) }),
+                        Err(e) => return Err(e),
+                    }
+                },
+                Some((index, '\\')) => {
+                    match escape(input, index) {
+                        Ok(c) => cs.push(c),
+                        Err(LexError::UnexpectedEof { .. }) => return Err(LexError::UnterminatedString { start, text : input.text_from(start) }),
+                        Err(e) => {
+                            skip_string(input);
+                            return Err(e);
+                        },
+                    }
+                },
+                Some((_, v)) => cs.push(v),
+                None => return Err(LexError::UnterminatedString { start, text : input.text_from(start) }),
+            }
+            v = input.next();
+        }
+
+        if parts.is_empty() {
+            Ok(Lexeme::String(cs))
+        }
+        else {
+            if !cs.is_empty() {
+                parts.push(StringPart::Literal(cs));
+            }
+            Ok(Lexeme::InterpolatedString(parts))
+        }
+    }
+}
+
+// Lexes the escape sequence following a backslash at the given index.
+fn escape<'a>(input : &mut Input<'a>, index : usize) -> Result<char, LexError> {
+    match input.next() {
+        Some((_, 't')) => Ok('\t'),
+        Some((_, 'n')) => Ok('\n'),
+        Some((_, 'r')) => Ok('\r'),
+        Some((_, '0')) => Ok('\0'),
+        Some((_, '\\')) => Ok('\\'),
+        Some((_, '"')) => Ok('"'),
+        Some((_, '\'')) => Ok('\''),
+        Some((_, '$')) => Ok('$'),
+        Some((_, 'u')) => unicode_escape(input, index),
+        Some((_, c)) => Err(LexError::BadEscape { index, text : format!("\\{}", c) }),
+        None => Err(input.unexpected()),
+    }
+}
+
+// Lexes the '{XXXX}' part of a '\u{XXXX}' escape.  Anything unexpected is left 
+// unconsumed so that a closing quote is never swallowed by a bad escape.
+fn unicode_escape<'a>(input : &mut Input<'a>, index : usize) -> Result<char, LexError> {
+    match input.peek() {
+        Some((_, '{')) => { input.next(); },
+        Some(_) => return Err(LexError::BadEscape { index, text : input.text_from(index) }),
+        None => return Err(input.unexpected()),
+    }
+
+    let mut value = 0;
+    let mut digits = 0;
+
+    loop {
+        match input.peek() {
+            Some((_, '}')) if digits > 0 => { input.next(); break; },
+            Some((_, c)) if c.is_ascii_hexdigit() && digits < 6 => {
+                value = value * 16 + c.to_digit(16).unwrap();
+                digits += 1;
+                input.next();
+            },
+            Some(_) => return Err(LexError::BadEscape { index, text : input.text_from(index) }),
+            None => return Err(input.unexpected()),
+        }
+    }
+
+    match std::char::from_u32(value) {
+        Some(c) => Ok(c),
+        None => Err(LexError::BadEscape { index, text : input.text_from(index) }),
+    }
+}
+
+// Consumes the rest of a string that is already known to be bad so that lexing can
+// resume after its closing quote.
+fn skip_string<'a>(input : &mut Input<'a>) {
+    loop {
+        match input.next() {
+            Some((_, '"')) | None => break,
+            Some((_, '\\')) => { input.next(); },
+            _ => { },
+        }
+    }
+}
+
+struct RawStringLexer {}
+
+impl Lexer for RawStringLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c == 'r'
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        let rp = input.restore_point();
+
+        let r = matches!( (input.next(), input.next()), (Some((_, 'r')), Some((_, '"'))) 
+                                                      | (Some((_, 'r')), Some((_, '#'))) );
+
+        input.restore(rp);
+        r
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let mut hashes = 0;
+
+        let start = input.index();
+        let rp = input.restore_point();
+
+        match input.next() {
+            Some((_, 'r')) => { },
+            _ => { input.restore(rp); return Err(input.unexpected()); },
+        }
+
+        while let Some((_, '#')) = input.peek() {
+            input.next();
+            hashes += 1;
+        }
+
+        // Until the quote this might just be a symbol starting with 'r', so give up 
+        // without consuming anything and let that win.
+        match input.next() {
+            Some((_, '"')) => { },
+            Some((index, c)) => { input.restore(rp); return Err(LexError::UnexpectedChar { index, c }) },
+            None => { input.restore(rp); return Err(input.unexpected()) },
+        }
+
+        let content_start = input.index();
+
+        loop {
+            match input.next() {
+                Some((end, '"')) => {
+                    let rp = input.restore_point();
+                    let mut closing = 0;
+
+                    while closing < hashes {
+                        match input.next() {
+                            Some((_, '#')) => closing += 1,
+                            _ => break,
+                        }
+                    }
+
+                    if closing == hashes {
+                        return Ok(Lexeme::String(input.src[content_start..end].to_string()));
+                    }
+
+                    input.restore(rp);
+                },
+                Some(_) => { },
+                None => return Err(LexError::UnterminatedString { start, text : input.text_from(start) }),
+            }
+        }
+    }
+}
+
+struct CharLexer {}
+
+impl Lexer for CharLexer {
+    fn starts_with(&self, c : char) -> bool {
+        c == '\''
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        matches!( input.peek(), Some((_, '\'')) )
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let start = input.index();
+        let rp = input.restore_point();
+
+        match input.next() {
+            Some((_, '\'')) => { },
+            _ => { input.restore(rp); return Err(input.unexpected()); },
+        }
+
+        let c = match input.next() {
+            Some((index, '\\')) => {
+                match escape(input, index) {
+                    Ok(c) => c,
+                    Err(LexError::UnexpectedEof { .. }) => return Err(LexError::UnterminatedChar { start, text : input.text_from(start) }),
+                    Err(e) => return Err(e),
+                }
+            },
+            Some((index, '\'')) => return Err(LexError::UnexpectedChar { index, c : '\'' }),
+            Some((_, c)) => c,
+            None => return Err(LexError::UnterminatedChar { start, text : input.text_from(start) }),
+        };
+
+        match input.peek() {
+            Some((_, '\'')) => { input.next(); Ok(Lexeme::Char(c)) },
+            _ => Err(LexError::UnterminatedChar { start, text : input.text_from(start) }),
+        }
+    }
+}
+
+struct KeywordLexer {
+    keyword : &'static str,
+    lexeme : Lexeme,
+}
+
+impl Lexer for KeywordLexer {
+    fn starts_with(&self, c : char) -> bool {
+        self.keyword.starts_with(c)
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        let rp = input.restore_point();
+        let r = self.lex(input).is_ok();
+        input.restore(rp);
+        r
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let rp = input.restore_point();
+
+        match identifier(input) {
+            Ok(name) if name == self.keyword => Ok(self.lexeme.clone()),
+            _ => { input.restore(rp); Err(input.unexpected()) },
+        }
+    }
+}
+
+struct PunctLexer<const X : usize>{
+    punct : [char; X],
+    lexeme : Lexeme,
+}
+
+impl<const X : usize> Lexer for PunctLexer<X> {
+    fn starts_with(&self, c : char) -> bool {
+        self.punct[0] == c
+    }
+
+    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
+        let rp = input.restore_point();
+
+        for x in self.punct {
+            match input.next() {
+                Some((_, c)) if c != x  => { input.restore(rp); return false; },
+                None => { input.restore(rp); return false; },
+                _ => { },
+            }
+        }
+
+        input.restore(rp);
+        true
+    }
+
+    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, LexError> {
+        let rp = input.restore_point();
+
+        for x in self.punct {
+            match input.next() {
+                Some((index, c)) if c != x  => { input.restore(rp); return Err(LexError::UnexpectedChar { index, c }); },
+                None => { input.restore(rp); return Err(LexError::UnexpectedEof { index : input.src.len() }); },
+                _ => { },
+            }
+        }
+
+        Ok(self.lexeme.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lex_should_attach_spans_to_tokens() {
+        let mut symbols = SymbolTable::new();
+        let r = lex(" \n  fun", &mut symbols).expect("lex should succeed");
+
+        assert_eq!( r.len(), 1 );
+        assert_eq!( r[0].lexeme, Lexeme::Fun );
+        assert_eq!( r[0].span, Span { start : 4, end : 7, line : 2, column : 3 } );
+    }
+
+    #[test]
+    fn lex_should_lex_entire_input() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("let x = 5; /* comment */ data Blah = Blah;", &mut symbols).expect("lex should succeed");
+
+        let lexemes = r.into_iter().map(|t| t.lexeme).collect::<Vec<_>>();
+
+        assert_eq!( lexemes, vec![ Lexeme::Let
+                                 , lower(&mut symbols, "x")
+                                 , Lexeme::Equal
+                                 , Lexeme::Integer(5)
+                                 , Lexeme::SemiColon
+                                 , Lexeme::Data
+                                 , upper(&mut symbols, "Blah")
+                                 , Lexeme::Equal
+                                 , upper(&mut symbols, "Blah")
+                                 , Lexeme::SemiColon
+                                 ] );
+    }
+
+    fn lower(symbols : &mut SymbolTable, s : &str) -> Lexeme { Lexeme::LowerCaseSymbol(symbols.intern(s)) }
+    fn upper(symbols : &mut SymbolTable, s : &str) -> Lexeme { Lexeme::UpperCaseSymbol(symbols.intern(s)) }
+    fn op(symbols : &mut SymbolTable, s : &str) -> Lexeme { Lexeme::Operator(symbols.intern(s)) }
+
+    #[test]
+    fn lex_should_follow_longest_match_table() {
+        let mut symbols = SymbolTable::new();
+
+        let table = vec![ ("(", vec![Lexeme::LParen])
+                        , (")", vec![Lexeme::RParen])
+                        , ("<", vec![Lexeme::LAngle])
+                        , (">", vec![Lexeme::RAngle])
+                        , ("{", vec![Lexeme::LCurl])
+                        , ("}", vec![Lexeme::RCurl])
+                        , ("[", vec![Lexeme::LSquare])
+                        , ("]", vec![Lexeme::RSquare])
+                        , ("|", vec![Lexeme::OrBar])
+                        , (";", vec![Lexeme::SemiColon])
+                        , (",", vec![Lexeme::Comma])
+                        , ("=", vec![Lexeme::Equal])
+                        , ("=>", vec![Lexeme::RightDoubleArrow])
+                        , ("= >", vec![Lexeme::Equal, Lexeme::RAngle])
+                        , ("==>", vec![op(&mut symbols, "==>")])
+                        , ("x=>y", vec![lower(&mut symbols, "x"), Lexeme::RightDoubleArrow, lower(&mut symbols, "y")])
+                        , ("fun", vec![Lexeme::Fun])
+                        , ("let", vec![Lexeme::Let])
+                        , ("data", vec![Lexeme::Data])
+                        , ("spec", vec![Lexeme::Spec])
+                        , ("match", vec![Lexeme::Match])
+                        , ("matches", vec![lower(&mut symbols, "matches")])
+                        , ("infixl", vec![Lexeme::Infixl])
+                        , ("infixr", vec![Lexeme::Infixr])
+                        , ("infix", vec![Lexeme::Infix])
+                        , ("infixes", vec![lower(&mut symbols, "infixes")])
+                        , ("if", vec![Lexeme::If])
+                        , ("iffy", vec![lower(&mut symbols, "iffy")])
+                        , ("else", vec![Lexeme::Else])
+                        , ("elsewhere", vec![lower(&mut symbols, "elsewhere")])
+                        , ("with", vec![Lexeme::With])
+                        , ("without", vec![lower(&mut symbols, "without")])
+                        , ("module", vec![Lexeme::Module])
+                        , ("import", vec![Lexeme::Import])
+                        , ("pub", vec![Lexeme::Pub])
+                        , ("public", vec![lower(&mut symbols, "public")])
+                        , ("List.map", vec![upper(&mut symbols, "List"), Lexeme::Dot, lower(&mut symbols, "map")])
+                        , ("!x", vec![Lexeme::Bang, lower(&mut symbols, "x")])
+                        , ("!=", vec![Lexeme::NotEqual])
+                        , ("! =", vec![Lexeme::Bang, Lexeme::Equal])
+                        , ("_", vec![Lexeme::Underscore])
+                        , ("_x", vec![lower(&mut symbols, "_x")])
+                        , ("__", vec![lower(&mut symbols, "__")])
+                        , ("x@y", vec![lower(&mut symbols, "x"), Lexeme::At, lower(&mut symbols, "y")])
+                        , ("\\x=>x", vec![Lexeme::Backslash, lower(&mut symbols, "x"), Lexeme::RightDoubleArrow, lower(&mut symbols, "x")])
+                        , ("funny", vec![lower(&mut symbols, "funny")])
+                        , ("fun_", vec![lower(&mut symbols, "fun_")])
+                        , ("Fun", vec![upper(&mut symbols, "Fun")])
+                        , ("letter", vec![lower(&mut symbols, "letter")])
+                        , ("spec1", vec![lower(&mut symbols, "spec1")])
+                        , ("database", vec![lower(&mut symbols, "database")])
+                        , ("fun(", vec![Lexeme::Fun, Lexeme::LParen])
+                        , ("true", vec![Lexeme::Bool(true)])
+                        , ("false", vec![Lexeme::Bool(false)])
+                        , ("trueish", vec![lower(&mut symbols, "trueish")])
+                        , ("falsey", vec![lower(&mut symbols, "falsey")])
+                        , ("True", vec![upper(&mut symbols, "True")])
+                        , ("blah", vec![lower(&mut symbols, "blah")])
+                        , ("Blah", vec![upper(&mut symbols, "Blah")])
+                        , ("_blah", vec![lower(&mut symbols, "_blah")])
+                        , ("->", vec![Lexeme::RightArrow])
+                        , ("+", vec![Lexeme::Plus])
+                        , ("-", vec![Lexeme::Minus])
+                        , ("*", vec![Lexeme::Star])
+                        , ("/", vec![Lexeme::Slash])
+                        , ("%", vec![Lexeme::Percent])
+                        , ("==", vec![Lexeme::DoubleEqual])
+                        , ("!=", vec![Lexeme::NotEqual])
+                        , ("<=", vec![Lexeme::LessEqual])
+                        , (">=", vec![Lexeme::GreaterEqual])
+                        , ("&&", vec![Lexeme::DoubleAmpersand])
+                        , ("||", vec![Lexeme::DoubleOrBar])
+                        , ("::", vec![Lexeme::DoubleColon])
+                        , (":", vec![Lexeme::Colon])
+                        , (".", vec![Lexeme::Dot])
+                        , ("..", vec![Lexeme::DotDot])
+                        , ("...", vec![Lexeme::DotDot, Lexeme::Dot])
+                        , ("..rest", vec![Lexeme::DotDot, lower(&mut symbols, "rest")])
+                        , ("1..", vec![Lexeme::Integer(1), Lexeme::DotDot])
+                        , ("x::xs", vec![lower(&mut symbols, "x"), Lexeme::DoubleColon, lower(&mut symbols, "xs")])
+                        , ("[1]-1", vec![Lexeme::LSquare, Lexeme::Integer(1), Lexeme::RSquare, Lexeme::Minus, Lexeme::Integer(1)])
+                        , ("===", vec![op(&mut symbols, "===")])
+                        , ("<=>", vec![op(&mut symbols, "<=>")])
+                        , (":::", vec![op(&mut symbols, ":::")])
+                        , ("|||", vec![op(&mut symbols, "|||")])
+                        , ("<$>", vec![op(&mut symbols, "<$>")])
+                        , ("$", vec![op(&mut symbols, "$")])
+                        , ("a|>b", vec![lower(&mut symbols, "a"), op(&mut symbols, "|>"), lower(&mut symbols, "b")])
+                        , ("m >>= f", vec![lower(&mut symbols, "m"), op(&mut symbols, ">>="), lower(&mut symbols, "f")])
+                        , ("a +// c", vec![lower(&mut symbols, "a"), Lexeme::Plus])
+                        , ("a+/* c */b", vec![lower(&mut symbols, "a"), Lexeme::Plus, lower(&mut symbols, "b")])
+                        , ("a <$>-1", vec![lower(&mut symbols, "a"), op(&mut symbols, "<$>-"), Lexeme::Integer(1)])
+                        , ("a->b", vec![lower(&mut symbols, "a"), Lexeme::RightArrow, lower(&mut symbols, "b")])
+                        , ("a/b", vec![lower(&mut symbols, "a"), Lexeme::Slash, lower(&mut symbols, "b")])
+                        , ("a.b", vec![lower(&mut symbols, "a"), Lexeme::Dot, lower(&mut symbols, "b")])
+                        , ("a-1", vec![lower(&mut symbols, "a"), Lexeme::Minus, Lexeme::Integer(1)])
+                        , ("a - 1", vec![lower(&mut symbols, "a"), Lexeme::Minus, Lexeme::Integer(1)])
+                        , ("a -1", vec![lower(&mut symbols, "a"), Lexeme::Minus, Lexeme::Integer(1)])
+                        , ("a - -1", vec![lower(&mut symbols, "a"), Lexeme::Minus, Lexeme::Integer(-1)])
+                        , ("1-1", vec![Lexeme::Integer(1), Lexeme::Minus, Lexeme::Integer(1)])
+                        , ("(1)-1", vec![Lexeme::LParen, Lexeme::Integer(1), Lexeme::RParen, Lexeme::Minus, Lexeme::Integer(1)])
+                        , ("(-1)", vec![Lexeme::LParen, Lexeme::Integer(-1), Lexeme::RParen])
+                        , ("= -1.5", vec![Lexeme::Equal, Lexeme::Decimal(-1.5)])
+                        , ("-x", vec![Lexeme::Minus, lower(&mut symbols, "x")])
+                        , ("1.x", vec![Lexeme::Integer(1), Lexeme::Dot, lower(&mut symbols, "x")])
+                        , ("123", vec![Lexeme::Integer(123)])
+                        , ("1.5", vec![Lexeme::Decimal(1.5)])
+                        , ("\"blah\"", vec![Lexeme::String("blah".to_string())])
+                        , ("\"fun\"", vec![Lexeme::String("fun".to_string())])
+                        , ("r\"fun\"", vec![Lexeme::String("fun".to_string())])
+                        , ("r#\"fun\"#", vec![Lexeme::String("fun".to_string())])
+                        , ("r", vec![lower(&mut symbols, "r")])
+                        , ("raw", vec![lower(&mut symbols, "raw")])
+                        , ("'f'", vec![Lexeme::Char('f')])
+                        , (" /* fun */ ", vec![])
+                        , (" // fun ", vec![])
+                        , ("/// fun", vec![Lexeme::DocComment(" fun".to_string())])
+                        , ("/** fun */", vec![Lexeme::DocComment(" fun ".to_string())])
+                        , ("//// fun", vec![])
+                        , ("/**/", vec![])
+                        ];
+
+        for (input, expected) in table {
+            let r = lex(input, &mut symbols).unwrap_or_else(|e| panic!("lex should succeed on {:?}: {:?}", input, e));
+            let lexemes = r.into_iter().map(|t| t.lexeme).collect::<Vec<_>>();
+            assert_eq!( lexemes, expected, "input: {:?}", input );
+        }
+    }
+
+    #[test]
+    fn lex_should_intern_repeated_symbols() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("blah Blah blah", &mut symbols).expect("lex should succeed");
+
+        match (&r[0].lexeme, &r[1].lexeme, &r[2].lexeme) {
+            (Lexeme::LowerCaseSymbol(a), Lexeme::UpperCaseSymbol(b), Lexeme::LowerCaseSymbol(c)) => {
+                assert_eq!( a, c );
+                assert_ne!( a, b );
+                assert_eq!( symbols.resolve(*a), "blah" );
+                assert_eq!( symbols.resolve(*b), "Blah" );
+            },
+            _ => panic!("expected symbols"),
+        }
+    }
+
+    #[test]
+    fn lex_should_lex_non_ascii_input() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("λx = \"héllo\" ;\n  Ωmega", &mut symbols).expect("lex should succeed");
+
+        assert_eq!( r[0].lexeme, lower(&mut symbols, "λx") );
+        assert_eq!( r[0].span, Span { start : 0, end : 3, line : 1, column : 1 } );
+        assert_eq!( r[1].span, Span { start : 4, end : 5, line : 1, column : 4 } );
+        assert_eq!( r[2].lexeme, Lexeme::String("héllo".to_string()) );
+        assert_eq!( r[2].span, Span { start : 6, end : 14, line : 1, column : 6 } );
+        assert_eq!( r[3].span, Span { start : 15, end : 16, line : 1, column : 14 } );
+        assert_eq!( r[4].lexeme, upper(&mut symbols, "Ωmega") );
+        assert_eq!( r[4].span, Span { start : 19, end : 25, line : 2, column : 3 } );
+    }
+
+    #[test]
+    fn lex_lossless_should_reproduce_source() {
+        let s = "  // header\r\n/// doc\nfun  add x /* a /* b */ */ y =\tx+y; // trailing\n\n  \r /**/ let s = \"${ x   }\"\n";
+        let mut symbols = SymbolTable::new();
+
+        let r = lex_lossless(s, &mut symbols).expect("lex_lossless should succeed");
+
+        assert_eq!( r.to_source(), s );
+    }
+
+    #[test]
+    fn lex_lossless_should_round_trip_without_source() {
+        let source = {
+            let s = String::from("let s = \"a ${ f  x }\"; /* c */\r\n  // d\nlet t = 'x';\n");
+            let mut symbols = SymbolTable::new();
+            lex_lossless(&s, &mut symbols).expect("lex_lossless should succeed").to_source()
+        };
+
+        assert_eq!( source, "let s = \"a ${ f  x }\"; /* c */\r\n  // d\nlet t = 'x';\n" );
+    }
+
+    #[test]
+    fn lex_lossless_should_attach_trivia_to_neighboring_tokens() {
+        let s = "a /* one */\n  // two\n  b // three\n";
+        let mut symbols = SymbolTable::new();
+
+        let r = lex_lossless(s, &mut symbols).expect("lex_lossless should succeed");
+
+        let kinds = |ts : &Vec<Trivia>| ts.iter().map(|t| t.kind).collect::<Vec<_>>();
+
+        assert_eq!( r.tokens.len(), 2 );
+
+        assert_eq!( kinds(&r.tokens[0].leading), vec![] );
+        assert_eq!( kinds(&r.tokens[0].trailing), vec![TriviaKind::Whitespace, TriviaKind::BlockComment] );
+        assert_eq!( r.tokens[0].trailing[1].span, Span { start : 2, end : 11, line : 1, column : 3 } );
+
+        assert_eq!( kinds(&r.tokens[1].leading), vec![ TriviaKind::Newline
+                                                     , TriviaKind::Whitespace
+                                                     , TriviaKind::LineComment
+                                                     , TriviaKind::Newline
+                                                     , TriviaKind::Whitespace
+                                                     ] );
+        assert_eq!( r.tokens[1].leading[2].span, Span { start : 14, end : 20, line : 2, column : 3 } );
+        assert_eq!( kinds(&r.tokens[1].trailing), vec![TriviaKind::Whitespace, TriviaKind::LineComment] );
+
+        assert_eq!( kinds(&r.eof), vec![TriviaKind::Newline] );
+    }
+
+    #[test]
+    fn lex_lossless_should_keep_trivia_of_empty_input() {
+        let s = " /* blah */ \n";
+        let mut symbols = SymbolTable::new();
+
+        let r = lex_lossless(s, &mut symbols).expect("lex_lossless should succeed");
+
+        assert!( r.tokens.is_empty() );
+        assert_eq!( r.eof.len(), 4 );
+        assert_eq!( r.eof[1].text, "/* blah */" );
+        assert_eq!( r.to_source(), s );
+    }
+
+    // Hands out the input a few bytes at a time so that tokens and characters get split 
+    // across reads.
+    struct Trickle<'a> {
+        bytes : &'a [u8],
+        size : usize,
+    }
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf : &mut [u8]) -> std::io::Result<usize> {
+            let len = self.size.min(buf.len()).min(self.bytes.len());
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn lex_reader_should_match_lex() {
+        let s = "/// doc\nfun add x /* a /* b */ */ y =\tx+y; // trailing\n\nlet Ωmega = \"α ${ x - 1 } β\";\nlet n = -1.5e3; let c = 'λ';\n\n";
+
+        for size in [1, 2, 3, 7, 64] {
+            let mut symbols = SymbolTable::new();
+            let expected = lex(s, &mut symbols).expect("lex should succeed");
+
+            let r = lex_reader(Trickle { bytes : s.as_bytes(), size }, &mut symbols).collect::<Result<Vec<_>, _>>();
+
+            assert_eq!( r, Ok(expected) );
+        }
+    }
+
+    // These take quadratic time if every refill rescans the pending token or the whole 
+    // line from its start.
+    #[test]
+    fn lex_reader_should_handle_huge_tokens_and_lines() {
+        let inputs = vec![ format!("let a = 1; /* {} */ let b = 2;", "x".repeat(200_000))
+                         , format!("let s = \"{}\";", "x".repeat(200_000))
+                         , "let a = f \"${ x }\n${ y }\"; ".repeat(20_000)
+                         ];
+
+        for s in inputs {
+            let mut symbols = SymbolTable::new();
+            let expected = lex(&s, &mut symbols).expect("lex should succeed");
+
+            let r = lex_reader(Trickle { bytes : s.as_bytes(), size : 16 }, &mut symbols).collect::<Result<Vec<_>, _>>();
+
+            assert_eq!( r, Ok(expected) );
+        }
+    }
+
+    #[test]
+    fn lex_reader_should_keep_spans_across_long_input() {
+        let s = "let a = 1;\n".repeat(2000);
+        let mut symbols = SymbolTable::new();
+        let expected = lex(&s, &mut symbols).expect("lex should succeed");
+
+        let r = lex_reader(s.as_bytes(), &mut symbols).collect::<Result<Vec<_>, _>>();
+
+        assert_eq!( r, Ok(expected) );
+    }
+
+    #[test]
+    fn lex_reader_should_report_lex_error_at_absolute_index() {
+        let s = format!("{}let s = \"abc", "let a = 1;\n".repeat(1000));
+        let mut symbols = SymbolTable::new();
+
+        let r = lex_reader(s.as_bytes(), &mut symbols).collect::<Result<Vec<_>, _>>();
+
+        assert_eq!( r, Err(LexError::UnterminatedString { start : 11008, text : "\"abc".to_string() }) );
+    }
+
+    #[test]
+    fn lex_reader_should_report_invalid_utf8_after_earlier_tokens() {
+        let bytes = b"let a = 1; \xFF 2";
+        let mut symbols = SymbolTable::new();
+
+        let r = lex_reader(Trickle { bytes, size : 3 }, &mut symbols).collect::<Vec<_>>();
+
+        assert_eq!( r.len(), 6 );
+        assert_eq!( r[4].as_ref().map(|t| t.lexeme.clone()), Ok(Lexeme::SemiColon) );
+        assert_eq!( r[5], Err(LexError::InvalidUtf8 { index : 11 }) );
+    }
+
+    #[test]
+    fn lex_reader_should_report_truncated_utf8() {
+        let bytes = b"let a = \xCE";
+        let mut symbols = SymbolTable::new();
+
+        let r = lex_reader(&bytes[..], &mut symbols).collect::<Vec<_>>();
+
+        assert_eq!( r.last(), Some(&Err(LexError::InvalidUtf8 { index : 8 })) );
+    }
+
+    #[test]
+    fn relex_should_match_full_lex() {
+        let table = vec![ ("fun add x y = x + y;\nlet z = add 1 2;\n", 14, 15, "-")
+                        , ("fun add x y = x + y;\nlet z = add 1 2;\n", 12, 13, "=>")
+                        , ("fun add x y = x + y;\nlet z = add 1 2;\n", 21, 21, "/* ")
+                        , ("fun add x y = x + y;\nlet z = add 1 2;\n", 8, 8, "\"")
+                        , ("let a = b 1;\nlet c = 2;\n", 9, 10, "- ")
+                        , ("let a = b - 1;\nlet c = 2;\n", 9, 11, "")
+                        , ("let a = 1;\nlet b = 2;\nlet c = 3;\n", 11, 11, "let x\n  = 0;\n")
+                        , ("let a = 1;\nlet b = 2;\nlet c = 3;\n", 0, 11, "")
+                        , ("let a = 1;\nlet b = 2;", 0, 0, "  ")
+                        , ("let a = 1;\nlet b = 2;", 21, 21, " 3;")
+                        , ("let a = 1.5;", 9, 11, "")
+                        , ("let s = \"${a}\"; let t = \"${b}\";\nc", 4, 5, "ss")
+                        , ("let a = x; /* b */ let c = y;", 13, 13, "*/ /*")
+                        , ("let a = x; /* b /* c */ d */ let e = y;", 17, 17, "/* ")
+                        , ("let s = \"a\nb\";\nlet t = 1;\n", 10, 10, "\n\n")
+                        , ("", 0, 0, "let a = 1;")
+                        , ("let a = 1;\nlet b = 2;", 10, 10, "/*")
+                        , ("let a = 1; /* b */", 16, 18, "")
+                        , ("let a = 1;", 0, 0, "/** doc\n")
+                        ];
+
+        for (s, start, end, text) in table {
+            let mut symbols = SymbolTable::new();
+            let old = lex(s, &mut symbols).expect("lex should succeed on old source");
+
+            let new_s = format!("{}{}{}", &s[..start], text, &s[end..]);
+            let edit = Edit { start, end, text : text.to_string() };
+
+            let expected = lex(&new_s, &mut symbols);
+            let r = relex(&old, &edit, &new_s, &mut symbols);
+
+            assert_eq!( r, expected, "{:?} -> {:?}", s, new_s );
+        }
+    }
+
+    #[test]
+    fn relex_should_report_errors_introduced_by_edit() {
+        let s = "let a = 1;";
+        let mut symbols = SymbolTable::new();
+        let old = lex(s, &mut symbols).expect("lex should succeed on old source");
+
+        let edit = Edit { start : 8, end : 8, text : "\"".to_string() };
+        let r = relex(&old, &edit, "let a = \"1;", &mut symbols);
+
+        assert!( matches!( r, Err(LexError::UnterminatedString { start : 8, .. }) ) );
+    }
+
+    #[test]
+    fn lex_should_report_unexpected_character() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("let x # 5;", &mut symbols);
+
+        assert_eq!( r, Err(LexError::UnexpectedChar { index : 6, c : '#' }) );
+    }
+
+    #[test]
+    fn lex_should_report_unterminated_comment() {
+        let table = vec![ "/* never closed"
+                        , "let a = 1;/* never closed"
+                        , "/** doc\nfun f = 1;"
+                        , "/* a /* b */ c"
+                        ];
+
+        for s in table {
+            let mut symbols = SymbolTable::new();
+
+            let r = lex(s, &mut symbols);
+            let (_, errors) = lex_with_recovery(s, &mut symbols);
 
-        match symbol_lexer.lex(input) {
-            Ok(Lexeme::LowerCaseSymbol(lexeme)) if lexeme == self.keyword => Ok(self.lexeme.clone()),
-            Ok(Lexeme::UpperCaseSymbol(lexeme)) if lexeme == self.keyword => Ok(self.lexeme.clone()),
-            _ => { input.restore(rp); Err(0) }, // TODO need a way to grab index
+            assert!( matches!( r, Err(LexError::UnterminatedComment { .. }) ), "{:?}: {:?}", s, r );
+            assert!( matches!( errors[..], [LexError::UnterminatedComment { .. }] ), "{:?}: {:?}", s, errors );
         }
     }
-}
 
-struct PunctLexer<const X : usize>{
-    punct : [char; X],
-    lexeme : Lexeme,
-}
+    #[test]
+    fn lex_should_report_unterminated_raw_string() {
+        let table = vec![ ("r#\"abc\"; let t = 1;", 0)
+                        , ("let s = r\"abc", 8)
+                        , ("let s = r##\"a\"#;", 8)
+                        ];
 
-impl<const X : usize> Lexer for PunctLexer<X> {
-    fn usable<'a>(&self, input : &mut Input<'a>) -> bool {
-        let rp = input.restore_point();
+        for (s, start) in table {
+            let mut symbols = SymbolTable::new();
 
-        for x in self.punct {
-            match input.next() {
-                Some((_, c)) if c != x  => { input.restore(rp); return false; },
-                None => return false,
-                _ => { },
-            }
+            let r = lex(s, &mut symbols);
+
+            assert_eq!( r, Err(LexError::UnterminatedString { start, text : s[start..].to_string() }), "{}", s );
         }
 
-        input.restore(rp);
-        true
+        let mut symbols = SymbolTable::new();
+
+        assert_eq!( lex("r#x", &mut symbols), Err(LexError::UnexpectedChar { index : 1, c : '#' }) );
     }
 
-    fn lex<'a>(&self, input : &mut Input<'a>) -> Result<Lexeme, usize> {
-        let rp = input.restore_point();
+    #[test]
+    fn lex_with_recovery_should_report_all_errors() {
+        let mut symbols = SymbolTable::new();
+        let (tokens, errors) = lex_with_recovery("let #x = 1.2.3; \"\\q\" y", &mut symbols);
+
+        assert_eq!( errors, vec![ LexError::UnexpectedChar { index : 4, c : '#' }
+                                , LexError::MalformedNumber { index : 9, text : "1.2.".to_string() }
+                                , LexError::BadEscape { index : 17, text : "\\q".to_string() }
+                                ] );
+
+        let lexemes = tokens.into_iter().map(|t| t.lexeme).collect::<Vec<_>>();
+
+        assert_eq!( lexemes, vec![ Lexeme::Let
+                                 , Lexeme::Equal
+                                 , Lexeme::SemiColon
+                                 , lower(&mut symbols, "y")
+                                 ] );
+    }
 
-        for x in self.punct {
-            match input.next() {
-                Some((index, c)) if c != x  => { input.restore(rp); return Err(index); },
-                None => { input.restore(rp); return Err(0); }, // TODO end of file
-                _ => { },
-            }
-        }
+    #[test]
+    fn lex_with_recovery_should_stop_at_unterminated_string() {
+        let mut symbols = SymbolTable::new();
+        let (tokens, errors) = lex_with_recovery("x \"blah", &mut symbols);
 
-        Ok(self.lexeme.clone())
+        assert_eq!( tokens.len(), 1 );
+        assert_eq!( errors, vec![ LexError::UnterminatedString { start : 2, text : "\"blah".to_string() } ] );
     }
-}
-// TODO sci notation lexer (?)
-// TODO add indices to lexemes
-
-#[cfg(test)]
-mod test {
-    use super::*;
 
     #[test]
     fn junk_lexer_usable_should_leave_input_alone_for_lonely_slash() {
         let lex = JunkLexer {};
-        let mut input = Input { cs : "/ *".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("/ *", &mut symbols);
 
         let result = lex.usable(&mut input);
 
-        assert_eq!( result, false );
+        assert!( !result );
 
         assert!( matches!( input.next(), Some((_, '/') ) ) );
     }
@@ -391,7 +2027,8 @@ mod test {
     #[test]
     fn junk_lexer_should_lex_whitespace() {
         let lex = JunkLexer {};
-        let mut input = Input { cs : "    \t \r \n a".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("    \t \r \n a", &mut symbols);
 
         let result = lex.lex(&mut input);
 
@@ -403,7 +2040,8 @@ mod test {
     #[test]
     fn junk_lexer_should_lex_whitespace_and_comment() {
         let lex = JunkLexer {};
-        let mut input = Input { cs : " /* blah blah blah */ a".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" /* blah blah blah */ a", &mut symbols);
 
         let result = lex.lex(&mut input);
 
@@ -415,7 +2053,8 @@ mod test {
     #[test]
     fn junk_lexer_should_lex_nested_comment() {
         let lex = JunkLexer {};
-        let mut input = Input { cs : " /* /* /* blah blah blah */ */ */ a".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" /* /* /* blah blah blah */ */ */ a", &mut symbols);
 
         let result = lex.lex(&mut input);
 
@@ -427,7 +2066,8 @@ mod test {
     #[test]
     fn junk_lexer_should_lex_almost_but_not_quite_comment_end() {
         let lex = JunkLexer {};
-        let mut input = Input { cs : " /* * / */ a".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" /* * / */ a", &mut symbols);
 
         let result = lex.lex(&mut input);
 
@@ -439,7 +2079,8 @@ mod test {
     #[test]
     fn junk_lexer_should_leave_lonely_slash() {
         let lex = JunkLexer {};
-        let mut input = Input { cs : " / ".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" / ", &mut symbols);
 
         let result = lex.lex(&mut input);
 
@@ -448,10 +2089,117 @@ mod test {
         assert!( matches!( input.next(), Some((_, '/') ) ) );
     }
 
+    #[test]
+    fn junk_lexer_should_fail_unterminated_comment() {
+        let lex = JunkLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" /* blah /* */ ", &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::UnterminatedComment { start : 1, text : "/* blah /* */ ".to_string() }) );
+    }
+
+    #[test]
+    fn junk_lexer_should_lex_comment_ending_in_extra_star() {
+        let lex = JunkLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" /* blah **/ a", &mut symbols);
+
+        let result = lex.lex(&mut input);
+
+        assert_eq!( result, Ok(Lexeme::Junk) );
+
+        assert!( matches!( input.next(), Some((_, 'a') ) ) );
+    }
+
+    #[test]
+    fn junk_lexer_should_lex_line_comment() {
+        let lex = JunkLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" // blah /* blah \n // \n//// blah\n a", &mut symbols);
+
+        let result = lex.lex(&mut input);
+
+        assert_eq!( result, Ok(Lexeme::Junk) );
+
+        assert!( matches!( input.next(), Some((_, 'a') ) ) );
+    }
+
+    #[test]
+    fn junk_lexer_should_stop_at_doc_comment() {
+        let lex = JunkLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(" /**/ /***/ /// blah", &mut symbols);
+
+        let result = lex.lex(&mut input);
+
+        assert_eq!( result, Ok(Lexeme::Junk) );
+        assert_eq!( input.index(), 12 );
+    }
+
+    #[test]
+    fn junk_lexer_usable_should_reject_doc_comment() {
+        let lex = JunkLexer {};
+
+        let mut symbols = SymbolTable::new();
+
+        assert!( !lex.usable(&mut Input::new("/// blah", &mut symbols)) );
+        assert!( !lex.usable(&mut Input::new("/** blah */", &mut symbols)) );
+    }
+
+    #[test]
+    fn doc_comment_lexer_should_lex_line_doc_comment() {
+        let lex = DocCommentLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("/// blah blah\r\na", &mut symbols);
+
+        let result = lex.lex(&mut input);
+
+        assert_eq!( result, Ok(Lexeme::DocComment(" blah blah".to_string())) );
+
+        assert!( matches!( input.next(), Some((_, '\n') ) ) );
+    }
+
+    #[test]
+    fn doc_comment_lexer_should_lex_block_doc_comment() {
+        let lex = DocCommentLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("/** blah /* nested */ blah */a", &mut symbols);
+
+        let result = lex.lex(&mut input);
+
+        assert_eq!( result, Ok(Lexeme::DocComment(" blah /* nested */ blah ".to_string())) );
+
+        assert!( matches!( input.next(), Some((_, 'a') ) ) );
+    }
+
+    #[test]
+    fn doc_comment_lexer_should_fail_unterminated_block_doc_comment() {
+        let lex = DocCommentLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("/** blah", &mut symbols);
+
+        let result = lex.lex(&mut input);
+
+        assert_eq!( result, Err(LexError::UnterminatedComment { start : 0, text : "/** blah".to_string() }) );
+    }
+
+    #[test]
+    fn lex_should_keep_doc_comments() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("// blah\n/// Adds things\nfun add x y = x + y;", &mut symbols).expect("lex should succeed");
+
+        assert_eq!( r[0].lexeme, Lexeme::DocComment(" Adds things".to_string()) );
+        assert_eq!( r[0].span, Span { start : 8, end : 23, line : 2, column : 1 } );
+        assert_eq!( r[1].lexeme, Lexeme::Fun );
+    }
+
     #[test]
     fn string_lexer_should_lex_string() {
         let lex = StringLexer {};
-        let mut input = Input { cs : r#""this is a \t \n \r \" \\ string""#.char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""this is a \t \n \r \" \\ string""#, &mut symbols);
 
         let r = lex.lex(&mut input).expect("StringLexer should lex string");
 
@@ -463,10 +2211,261 @@ mod test {
         assert_eq!( input.next(), None );
     }
 
+    #[test]
+    fn string_lexer_should_fail_unterminated_string() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""blah \""#, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::UnterminatedString { start : 0, text : r#""blah \""#.to_string() }) );
+    }
+
+    #[test]
+    fn string_lexer_should_fail_bad_escape() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""blah \q""#, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::BadEscape { index : 6, text : r"\q".to_string() }) );
+    }
+
+    #[test]
+    fn string_lexer_should_lex_unicode_and_null_escapes() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""\u{41}\u{1F600}\0\'""#, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Ok(Lexeme::String("A\u{1F600}\0'".to_string())) );
+        assert_eq!( input.next(), None );
+    }
+
+    #[test]
+    fn string_lexer_should_fail_bad_unicode_escapes() {
+        let table = vec![ (r#""\u41""#, r"\u")
+                        , (r#""\u{}""#, r"\u{")
+                        , (r#""\u{zz}""#, r"\u{")
+                        , (r#""\u{1234567}""#, r"\u{123456")
+                        , (r#""\u{D800}""#, r"\u{D800}")
+                        , (r#""\u{110000}""#, r"\u{110000}")
+                        ];
+
+        for (s, text) in table {
+            let lex = StringLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Err(LexError::BadEscape { index : 1, text : text.to_string() }), "input: {:?}", s );
+            assert_eq!( input.next(), None, "input: {:?}", s );
+        }
+    }
+
+    #[test]
+    fn string_lexer_should_fail_unterminated_unicode_escape() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""\u{41"#, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::UnterminatedString { start : 0, text : r#""\u{41"#.to_string() }) );
+    }
+
+    fn code_lexemes(part : &StringPart) -> Vec<Lexeme> {
+        match part {
+            StringPart::Code(tokens) => tokens.iter().map(|t| t.lexeme.clone()).collect(),
+            _ => panic!("expected code"),
+        }
+    }
+
+    #[test]
+    fn string_lexer_should_split_interpolated_string() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""hello ${name}! $5 \${x}""#, &mut symbols);
+
+        let r = lex.lex(&mut input).expect("StringLexer should lex interpolated string");
+
+        let parts = match r {
+            Lexeme::InterpolatedString(parts) => parts,
+            _ => panic!("expected interpolated string"),
+        };
+
+        assert_eq!( parts.len(), 3 );
+        assert_eq!( parts[0], StringPart::Literal("hello ".to_string()) );
+        assert_eq!( code_lexemes(&parts[1]), vec![lower(input.symbols, "name")] );
+        assert_eq!( parts[2], StringPart::Literal("! $5 ${x}".to_string()) );
+        assert_eq!( input.next(), None );
+    }
+
+    #[test]
+    fn string_lexer_should_handle_nested_braces_and_strings() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""${ {x} }${f("}${y}")}""#, &mut symbols);
+
+        let r = lex.lex(&mut input).expect("StringLexer should lex interpolated string");
+
+        let parts = match r {
+            Lexeme::InterpolatedString(parts) => parts,
+            _ => panic!("expected interpolated string"),
+        };
+
+        assert_eq!( parts.len(), 2 );
+        assert_eq!( code_lexemes(&parts[0]), vec![Lexeme::LCurl, lower(input.symbols, "x"), Lexeme::RCurl] );
+
+        let inner = code_lexemes(&parts[1]);
+        assert_eq!( inner.len(), 4 );
+        assert_eq!( inner[0], lower(input.symbols, "f") );
+        assert_eq!( inner[1], Lexeme::LParen );
+        assert_eq!( inner[3], Lexeme::RParen );
+
+        match &inner[2] {
+            Lexeme::InterpolatedString(parts) => {
+                assert_eq!( parts[0], StringPart::Literal("}".to_string()) );
+                assert_eq!( code_lexemes(&parts[1]), vec![lower(input.symbols, "y")] );
+            },
+            _ => panic!("expected nested interpolated string"),
+        }
+
+        assert_eq!( input.next(), None );
+    }
+
+    #[test]
+    fn string_lexer_should_fail_unterminated_interpolation() {
+        let lex = StringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#""a ${b"#, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::UnterminatedString { start : 0, text : r#""a ${b"#.to_string() }) );
+    }
+
+    #[test]
+    fn lex_should_give_interpolated_tokens_source_spans() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("x\n\"a\n${ b - 1 }\" c", &mut symbols).expect("lex should succeed");
+
+        assert_eq!( r[1].span, Span { start : 2, end : 16, line : 2, column : 1 } );
+        assert_eq!( r[2].span, Span { start : 17, end : 18, line : 3, column : 13 } );
+
+        let parts = match &r[1].lexeme {
+            Lexeme::InterpolatedString(parts) => parts,
+            _ => panic!("expected interpolated string"),
+        };
+
+        match &parts[1] {
+            StringPart::Code(tokens) => {
+                assert_eq!( tokens[0].span, Span { start : 8, end : 9, line : 3, column : 4 } );
+                assert_eq!( tokens[1].lexeme, Lexeme::Minus );
+                assert_eq!( tokens[2].span, Span { start : 12, end : 13, line : 3, column : 8 } );
+            },
+            _ => panic!("expected code"),
+        }
+    }
+
+    #[test]
+    fn raw_string_lexer_should_lex_raw_string() {
+        let lex = RawStringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r#"r"\d+\n"s"#, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Ok(Lexeme::String(r"\d+\n".to_string())) );
+        assert!( matches!( input.next(), Some((_, 's'))) );
+    }
+
+    #[test]
+    fn raw_string_lexer_should_lex_hashed_raw_string() {
+        let lex = RawStringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r###"r##"{"a": "#b"}"#"##s"###, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Ok(Lexeme::String(r##"{"a": "#b"}"#"##.to_string())) );
+        assert!( matches!( input.next(), Some((_, 's'))) );
+    }
+
+    #[test]
+    fn raw_string_lexer_should_fail_unterminated_raw_string() {
+        let lex = RawStringLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new(r##"r#"blah" "##, &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::UnterminatedString { start : 0, text : r##"r#"blah" "##.to_string() }) );
+    }
+
+    #[test]
+    fn char_lexer_should_lex_chars() {
+        let table = vec![ ("'a'", 'a')
+                        , ("'\"'", '"')
+                        , (r"'\''", '\'')
+                        , (r"'\n'", '\n')
+                        , (r"'\u{3bb}'", 'λ')
+                        , ("'λ'", 'λ')
+                        ];
+
+        for (s, expected) in table {
+            let lex = CharLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Ok(Lexeme::Char(expected)), "input: {:?}", s );
+            assert_eq!( input.next(), None );
+        }
+    }
+
+    #[test]
+    fn char_lexer_should_fail_bad_chars() {
+        let table = vec![ ("''", LexError::UnexpectedChar { index : 1, c : '\'' })
+                        , ("'ab'", LexError::UnterminatedChar { start : 0, text : "'a".to_string() })
+                        , ("'a", LexError::UnterminatedChar { start : 0, text : "'a".to_string() })
+                        , (r"'\", LexError::UnterminatedChar { start : 0, text : r"'\".to_string() })
+                        , (r"'\q'", LexError::BadEscape { index : 1, text : r"\q".to_string() })
+                        ];
+
+        for (s, expected) in table {
+            let lex = CharLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Err(expected), "input: {:?}", s );
+        }
+    }
+
+    #[test]
+    fn lex_should_track_lines_across_multi_line_strings() {
+        let mut symbols = SymbolTable::new();
+        let r = lex("\"a\nb\nc\" x\n  'y'", &mut symbols).expect("lex should succeed");
+
+        assert_eq!( r[0].lexeme, Lexeme::String("a\nb\nc".to_string()) );
+        assert_eq!( r[0].span, Span { start : 0, end : 7, line : 1, column : 1 } );
+        assert_eq!( r[1].span, Span { start : 8, end : 9, line : 3, column : 4 } );
+        assert_eq!( r[2].lexeme, Lexeme::Char('y') );
+        assert_eq!( r[2].span, Span { start : 12, end : 15, line : 4, column : 3 } );
+    }
+
     #[test]
     fn bool_lexer_should_lex_true() {
         let lex = BoolLexer {};
-        let mut input = Input { cs : "true".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("true", &mut symbols);
 
         let r = lex.lex(&mut input).expect("BoolLexer should lex bool");
 
@@ -476,7 +2475,8 @@ mod test {
     #[test]
     fn bool_lexer_should_lex_false() {
         let lex = BoolLexer {};
-        let mut input = Input { cs : "false".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("false", &mut symbols);
 
         let r = lex.lex(&mut input).expect("BoolLexer should lex bool");
 
@@ -486,11 +2486,12 @@ mod test {
     #[test]
     fn bool_lexer_should_not_consume_boolish_symbol() {
         let lex = BoolLexer {};
-        let mut input = Input { cs : "trueish".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("trueish", &mut symbols);
 
         let r = lex.lex(&mut input);
 
-        assert!( matches!( r, Err(_) ) );
+        assert!( r.is_err() );
 
         assert!( matches!( input.next(), Some((_, 't')) ) );
     }
@@ -498,12 +2499,13 @@ mod test {
     #[test]
     fn symbol_lexer_should_lex_upper_case_symbol() {
         let lex = SymbolLexer {};
-        let mut input = Input { cs : "Blah__123".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("Blah__123", &mut symbols);
 
         let r = lex.lex(&mut input).expect("SymbolLexer should lex symbol");
 
         match r {
-            Lexeme::UpperCaseSymbol(s) => assert_eq!( s, "Blah__123" ),
+            Lexeme::UpperCaseSymbol(s) => assert_eq!( symbols.resolve(s), "Blah__123" ),
             _ => panic!("expected upper case symbol"),
         }
     }
@@ -511,12 +2513,13 @@ mod test {
     #[test]
     fn symbol_lexer_should_lex_lower_case_symbol() {
         let lex = SymbolLexer {};
-        let mut input = Input { cs : "blah__123".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("blah__123", &mut symbols);
 
         let r = lex.lex(&mut input).expect("SymbolLexer should lex symbol");
 
         match r {
-            Lexeme::LowerCaseSymbol(s) => assert_eq!( s, "blah__123" ),
+            Lexeme::LowerCaseSymbol(s) => assert_eq!( symbols.resolve(s), "blah__123" ),
             _ => panic!("expected upper case symbol"),
         }
     }
@@ -524,7 +2527,8 @@ mod test {
     #[test]
     fn number_lexer_should_lex_standard_integer() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "1234".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1234", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
@@ -534,29 +2538,32 @@ mod test {
     #[test]
     fn number_lexer_should_not_conume_ending_input() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "1234s".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1234;", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
         assert_eq!( r, Lexeme::Integer(1234) );
-        assert!( matches!( input.next(), Some((_, 's'))) );
+        assert!( matches!( input.next(), Some((_, ';'))) );
     }
 
     #[test]
     fn number_lexer_should_lex_negative_input() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "-1234s".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("-1234;", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
         assert_eq!( r, Lexeme::Integer(-1234) );
-        assert!( matches!( input.next(), Some((_, 's'))) );
+        assert!( matches!( input.next(), Some((_, ';'))) );
     }
 
     #[test]
     fn number_lexer_should_stop_and_ignore_trailing_dot_on_decimal() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "1234.5678.".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1234.5678.", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
@@ -567,7 +2574,8 @@ mod test {
     #[test]
     fn number_lexer_should_stop_and_ignore_trailing_dot_on_integer() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "1234.".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1234.", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
@@ -578,32 +2586,192 @@ mod test {
     #[test]
     fn number_lexer_should_lex_decimal() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "1234.5678s".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1234.5678;", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
         assert_eq!( r, Lexeme::Decimal(1234.5678) );
-        assert!( matches!( input.next(), Some((_, 's'))) );
+        assert!( matches!( input.next(), Some((_, ';'))) );
     }
 
     #[test]
     fn number_lexer_should_fail_second_dot() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "-1234.5678.99s".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("-1234.5678.99s", &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::MalformedNumber { index : 0, text : "-1234.5678.".to_string() }) );
+    }
+
+    #[test]
+    fn number_lexer_should_fail_lonely_minus() {
+        let lex = NumberLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("-s", &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::MalformedNumber { index : 0, text : "-".to_string() }) );
+    }
+
+    #[test]
+    fn number_lexer_should_lex_radix_integers() {
+        let table = vec![ ("0xFF_FF", 0xFFFF)
+                        , ("0xdeadBEEF", 0xdeadbeef)
+                        , ("-0x10", -16)
+                        , ("0o777", 0o777)
+                        , ("0b1010_1010", 0b1010_1010)
+                        , ("0x7FFF_FFFF_FFFF_FFFF", i64::MAX)
+                        , ("-0x8000_0000_0000_0000", i64::MIN)
+                        ];
+
+        for (s, expected) in table {
+            let lex = NumberLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Ok(Lexeme::Integer(expected)), "input: {:?}", s );
+            assert_eq!( input.next(), None );
+        }
+    }
+
+    #[test]
+    fn number_lexer_should_report_non_radix_digit() {
+        let lex = NumberLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("0b102", &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::MalformedNumber { index : 0, text : "0b102".to_string() }) );
+        assert_eq!( input.next(), None );
+    }
+
+    #[test]
+    fn number_lexer_should_fail_radix_prefix_without_digits() {
+        let lex = NumberLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("0x_g", &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Err(LexError::MalformedNumber { index : 0, text : "0x_".to_string() }) );
+    }
+
+    #[test]
+    fn number_lexer_should_lex_digit_separators() {
+        let lex = NumberLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1_000_000.000_1;", &mut symbols);
+
+        let r = lex.lex(&mut input);
+
+        assert_eq!( r, Ok(Lexeme::Decimal(1000000.0001)) );
+        assert!( matches!( input.next(), Some((_, ';'))) );
+    }
+
+    #[test]
+    fn number_lexer_should_lex_scientific_notation() {
+        let table = vec![ ("1e10", 1e10)
+                        , ("1E10", 1e10)
+                        , ("1.5e-3", 1.5e-3)
+                        , ("-2.5e+2", -2.5e2)
+                        , ("6.02e2_3", 6.02e23)
+                        ];
+
+        for (s, expected) in table {
+            let lex = NumberLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Ok(Lexeme::Decimal(expected)), "input: {:?}", s );
+            assert_eq!( input.next(), None );
+        }
+    }
+
+    #[test]
+    fn number_lexer_should_leave_e_without_exponent() {
+        let lex = NumberLexer {};
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("1e;", &mut symbols);
 
         let r = lex.lex(&mut input);
 
-        assert!( matches!( r, Err(_) ) );
+        assert_eq!( r, Err(LexError::MalformedNumber { index : 0, text : "1e".to_string() }) );
+        assert!( matches!( input.next(), Some((_, ';'))) );
+    }
+
+    #[test]
+    fn number_lexer_should_report_digits_run_on() {
+        let table = vec![ ("0b1012", "0b1012")
+                        , ("0xFFg;", "0xFFg")
+                        , ("0o78", "0o78")
+                        , ("123abc", "123abc")
+                        , ("1else", "1else")
+                        , ("1.5x", "1.5x")
+                        , ("1_", "1_")
+                        , ("1__", "1__")
+                        , ("1_.5", "1_.5")
+                        , ("0x_", "0x_")
+                        , ("0xF_ ", "0xF_")
+                        , ("1e5_", "1e5_")
+                        ];
+
+        for (s, expected) in table {
+            let lex = NumberLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Err(LexError::MalformedNumber { index : 0, text : expected.to_string() }), "{}", s );
+        }
+    }
+
+    #[test]
+    fn lex_should_report_digits_run_on() {
+        let mut symbols = SymbolTable::new();
+
+        assert_eq!( lex("let x = 0b1012;", &mut symbols), Err(LexError::MalformedNumber { index : 8, text : "0b1012".to_string() }) );
+        assert_eq!( lex("let x = 1_;", &mut symbols), Err(LexError::MalformedNumber { index : 8, text : "1_".to_string() }) );
+        assert!( lex("let x = 1_000 + 0x_FF;", &mut symbols).is_ok() );
+    }
+
+    #[test]
+    fn number_lexer_should_report_overflow() {
+        let table = vec![ "9223372036854775808"
+                        , "-9223372036854775809"
+                        , "0x1_0000_0000_0000_0000"
+                        , "1e999"
+                        ];
+
+        for s in table {
+            let lex = NumberLexer {};
+            let mut symbols = SymbolTable::new();
+            let mut input = Input::new(s, &mut symbols);
+
+            let r = lex.lex(&mut input);
+
+            assert_eq!( r, Err(LexError::NumberOverflow { index : 0, text : s.to_string() }) );
+        }
     }
 
     #[test]
     fn number_lexer_should_lex_negative_decimal() {
         let lex = NumberLexer {};
-        let mut input = Input { cs : "-1234.5678s".char_indices().peekable() };
+        let mut symbols = SymbolTable::new();
+        let mut input = Input::new("-1234.5678;", &mut symbols);
 
         let r = lex.lex(&mut input).expect("NumberLexer should lex standard integer");
 
         assert_eq!( r, Lexeme::Decimal(-1234.5678) );
-        assert!( matches!( input.next(), Some((_, 's'))) );
+        assert!( matches!( input.next(), Some((_, ';'))) );
     }
-}
\ No newline at end of file
+}
@@ -1,36 +1,137 @@
+use super::symbol::Symbol;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Span {
+    pub start : usize,
+    pub end : usize,
+    pub line : usize,
+    pub column : usize,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Token {
+    pub lexeme : Lexeme,
+    pub span : Span,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+}
+
+// The text is borrowed from the source that was lexed.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Trivia<'a> {
+    pub kind : TriviaKind,
+    pub span : Span,
+    pub text : &'a str,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct LosslessToken<'a> {
+    pub leading : Vec<Trivia<'a>>,
+    pub token : Token,
+    // The token as it was written, which the lexeme doesn't keep.
+    pub text : &'a str,
+    pub trailing : Vec<Trivia<'a>>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct LosslessTokens<'a> {
+    pub tokens : Vec<LosslessToken<'a>>,
+    pub eof : Vec<Trivia<'a>>,
+}
+
+impl<'a> LosslessTokens<'a> {
+    pub fn to_source(&self) -> String {
+        let mut ret = String::new();
+
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                ret.push_str(trivia.text);
+            }
+            ret.push_str(token.text);
+            for trivia in &token.trailing {
+                ret.push_str(trivia.text);
+            }
+        }
+
+        for trivia in &self.eof {
+            ret.push_str(trivia.text);
+        }
+
+        ret
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum StringPart {
+    Literal(String),
+    Code(Vec<Token>),
+}
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Lexeme {
     Junk,
+    DocComment(String),
     RParen,
     LParen,
     RAngle,
     LAngle,
     RCurl,
     LCurl,
+    RSquare,
+    LSquare,
     RightDoubleArrow,
     OrBar,
     Fun,
     Let,
     Data,
     Spec,
+    Match,
+    Infixl,
+    Infixr,
+    Infix,
+    If,
+    Else,
+    With,
+    Module,
+    Import,
+    Pub,
     SemiColon,
     Comma,
     Equal,
-    LowerCaseSymbol(String),
-    UpperCaseSymbol(String),
+    RightArrow,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    DoubleEqual,
+    NotEqual,
+    LessEqual,
+    GreaterEqual,
+    DoubleAmpersand,
+    DoubleOrBar,
+    DoubleColon,
+    Colon,
+    Dot,
+    DotDot,
+    At,
+    Backslash,
+    Bang,
+    Underscore,
+    // Any run of operator characters that isn't one of the built in operators above.
+    Operator(Symbol),
+    LowerCaseSymbol(Symbol),
+    UpperCaseSymbol(Symbol),
     Bool(bool),
     Integer(i64),
     Decimal(f64),
     String(String),
+    InterpolatedString(Vec<StringPart>),
+    Char(char),
 }
-
-impl Lexeme {
-    pub fn start_index(&self) -> usize {
-        0
-    }
-
-    pub fn end_index(&self) -> usize {
-        0
-    }
-}
\ No newline at end of file
@@ -1,6 +1,10 @@
+pub mod lexeme;
+pub mod lex;
+pub mod parser;
+pub mod combinator;
+pub mod capture;
+pub mod desugar;
+pub mod prelude;
+pub mod symbol;
 
-mod lexeme;
-mod lex;
-mod parser;
-
-pub mod ast;
\ No newline at end of file
+pub mod ast;
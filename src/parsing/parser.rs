@@ -1,11 +1,2117 @@
 
-use std::iter::{Peekable, Enumerate};
+use std::collections::HashMap;
 
-use super::lexeme::Lexeme;
-use super::ast::Ast;
+use super::lex::lex;
+use super::lexeme::{Lexeme, StringPart, Token, Span};
+use super::symbol::{Symbol, SymbolTable};
+use super::capture;
+use super::combinator::{delimited, optional, recover, rule, sep_by, sep_by1, Delimited, Rule, SepBy};
+use super::ast::{Assoc, Ast, Constructor, Expr, ExprKind, FieldPattern, FieldValue, Ident, MatchArm, Pattern, PatternKind, Type, TypeKind};
 
+static PARSERS : [&dyn Parser<Output = Ast>; 8] = [ &FunParser{}
+                                    , &LetParser{}
+                                    , &SpecParser{}
+                                    , &DataParser{}
+                                    , &FixityParser{}
+                                    , &ModuleParser{}
+                                    , &ImportParser{}
+                                    , &PubParser{}
+                                    ];
 
-pub trait Parser {
-    fn usable(&self, input : &mut Peekable<Enumerate<std::vec::IntoIter<Lexeme>>>) -> bool;
-    fn parse(&self, input : &mut Peekable<Enumerate<std::vec::IntoIter<Lexeme>>>) -> Result<Ast, (usize, usize)>;
-}
\ No newline at end of file
+// Declarations that can be made public with 'pub'.
+static PUBLIC : [&dyn Parser<Output = Ast>; 4] = [ &FunParser{}
+                                   , &LetParser{}
+                                   , &DataParser{}
+                                   , &FixityParser{}
+                                   ];
+
+static STATEMENTS : [&dyn Parser<Output = Ast>; 2] = [ &LetParser{}
+                                       , &SpecParser{}
+                                       ];
+
+// Fixities of the built in operators.  Fixity declarations in a module can override these.
+static DEFAULT_FIXITIES : [(&str, Assoc, u8); 14] = [ ("||", Assoc::Right, 2)
+                                                    , ("&&", Assoc::Right, 3)
+                                                    , ("==", Assoc::NonAssoc, 4)
+                                                    , ("!=", Assoc::NonAssoc, 4)
+                                                    , ("<", Assoc::NonAssoc, 4)
+                                                    , (">", Assoc::NonAssoc, 4)
+                                                    , ("<=", Assoc::NonAssoc, 4)
+                                                    , (">=", Assoc::NonAssoc, 4)
+                                                    , ("::", Assoc::Right, 5)
+                                                    , ("+", Assoc::Left, 6)
+                                                    , ("-", Assoc::Left, 6)
+                                                    , ("*", Assoc::Left, 7)
+                                                    , ("/", Assoc::Left, 7)
+                                                    , ("%", Assoc::Left, 7)
+                                                    ];
+
+const MAX_PRECEDENCE : u8 = 9;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Expected {
+    Lexeme(Lexeme),
+    LowerCaseSymbol,
+    UpperCaseSymbol,
+    // Either a lower or an upper case symbol.
+    Name,
+    Declaration,
+    Expr,
+    Pattern,
+    Type,
+    Operator,
+    Precedence,
+    // The operator found is non-associative and has the same precedence as the one 
+    // before it, so the two need brackets, as in '(a == b) == c'.
+    NonAssociative,
+    // An operator that isn't built in, since only those can have a public fixity.
+    UserOperator,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub expected : Vec<Expected>,
+    // None at the end of the input.
+    pub found : Option<Lexeme>,
+    pub span : Span,
+}
+
+pub trait Parser : Sync {
+    type Output;
+
+    fn usable(&self, input : &mut Input) -> bool;
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError>;
+}
+
+impl<P : Parser + ?Sized> Parser for &P {
+    type Output = P::Output;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        (**self).usable(input)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Self::Output, ParseError> {
+        (**self).parse(input)
+    }
+}
+
+/// Parses the output of `lex` into the list of top level declarations that make up
+/// a module.
+pub fn parse_module(tokens : Vec<Token>, symbols : &mut SymbolTable) -> Result<Vec<Ast>, ParseError> {
+    parse_module_with_fixities(tokens, symbols, &[])
+}
+
+/// Parses a module using the public fixities of the operators it imports.  The 
+/// module's own fixity declarations take precedence over them.
+pub fn parse_module_with_fixities(tokens : Vec<Token>, symbols : &mut SymbolTable, fixities : &[(Symbol, Assoc, u8)]) -> Result<Vec<Ast>, ParseError> {
+    let (decls, mut errors) = parse_input(tokens, symbols, fixities, false);
+
+    if errors.is_empty() {
+        Ok(decls)
+    }
+    else {
+        Err(errors.remove(0))
+    }
+}
+
+/// Parses every declaration that it can.  After an error the rest of the broken 
+/// declaration is skipped so that errors in later declarations are reported too.  A 
+/// broken statement in a block or arm in a match is skipped on its own, so the 
+/// declaration around it is kept and can report more errors.
+pub fn parse_module_with_recovery(tokens : Vec<Token>, symbols : &mut SymbolTable) -> (Vec<Ast>, Vec<ParseError>) {
+    parse_input(tokens, symbols, &[], true)
+}
+
+fn parse_input(tokens : Vec<Token>, symbols : &mut SymbolTable, imported : &[(Symbol, Assoc, u8)], recover : bool) -> (Vec<Ast>, Vec<ParseError>) {
+    let mut input = Input::new(tokens, symbols);
+    input.recovering = recover;
+    input.fixities.extend(imported.iter().map(|(op, assoc, precedence)| (*op, (*assoc, *precedence))));
+    let mut decls = vec![];
+
+    // Fixity declarations apply to the whole module, including code that comes before 
+    // them, so they are parsed first.  Broken ones are left for the main pass to report.
+    let fixity = FixityParser {};
+    for i in 0..input.tokens.len() {
+        input.index = i;
+        if fixity.usable(&mut input) {
+            if let Ok(Ast::Fixity { assoc, precedence, op, .. }) = fixity.parse(&mut input) {
+                input.fixities.insert(op.symbol, (assoc, precedence));
+            }
+        }
+    }
+    input.index = 0;
+    input.expected.clear();
+
+    while input.peek().is_some() {
+        let start = input.index;
+        let r = match PARSERS.iter().find(|p| p.usable(&mut input)) {
+            Some(parser) => parser.parse(&mut input),
+            None => Err(input.error(Expected::Declaration)),
+        };
+
+        match r {
+            Ok(mut decl) => {
+                capture::fill_captures(&mut decl);
+                decls.push(decl);
+            },
+            Err(e) => {
+                input.recovered.push(e);
+                if !recover {
+                    break;
+                }
+                synchronize(&mut input, start);
+            },
+        }
+    }
+
+    (decls, input.recovered)
+}
+
+fn is_declaration(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::Fun 
+                    | Lexeme::Let 
+                    | Lexeme::Spec 
+                    | Lexeme::Data 
+                    | Lexeme::Infixl 
+                    | Lexeme::Infixr 
+                    | Lexeme::Infix 
+                    | Lexeme::Module 
+                    | Lexeme::Import 
+                    | Lexeme::Pub 
+                    )
+}
+
+// Skips the rest of a declaration that failed to parse.  Braces are counted from the
+// start of the declaration so that a ';', '}' or keyword inside of a block doesn't end 
+// the skipping early.  Stops after a ';' or an unmatched '}', or before the keyword of 
+// the next declaration.
+fn synchronize(input : &mut Input, start : usize) {
+    let mut depth = 0;
+    for t in &input.tokens[start..input.index] {
+        match t.lexeme {
+            Lexeme::LCurl => depth += 1,
+            Lexeme::RCurl => depth -= 1,
+            _ => { },
+        }
+    }
+
+    // Always make progress past the start of the broken declaration.
+    if input.index == start {
+        input.next();
+    }
+
+    while let Some(lexeme) = input.peek() {
+        match lexeme {
+            Lexeme::SemiColon if depth <= 0 => { input.next(); return; },
+            Lexeme::RCurl if depth <= 0 => { input.next(); return; },
+            l if depth <= 0 && is_declaration(l) => return,
+            Lexeme::LCurl => depth += 1,
+            Lexeme::RCurl => depth -= 1,
+            _ => { },
+        }
+        input.next();
+    }
+}
+
+// Skips the rest of a statement or match arm that failed to parse, stopping before the 
+// separator that ends it or the '}' that closes the block or match around it.  Brackets 
+// are counted from the start of the item so that ones inside of it don't count.  Never 
+// skips into the next declaration.
+fn skip_item(input : &mut Input, start : usize, separator : &Lexeme) {
+    let nesting = |lexeme : &Lexeme| match lexeme {
+        Lexeme::LCurl | Lexeme::LParen | Lexeme::LSquare => 1,
+        Lexeme::RCurl | Lexeme::RParen | Lexeme::RSquare => -1,
+        _ => 0,
+    };
+
+    let mut depth = 0;
+    let mut braces = 0;
+    for t in &input.tokens[start..input.index] {
+        depth += nesting(&t.lexeme);
+        braces += match t.lexeme { Lexeme::LCurl => 1, Lexeme::RCurl => -1, _ => 0 };
+    }
+
+    while let Some(lexeme) = input.peek() {
+        match lexeme {
+            l if depth <= 0 && l == separator => return,
+            Lexeme::RCurl if braces <= 0 => return,
+            Lexeme::Let | Lexeme::Spec => { },
+            l if is_declaration(l) => return,
+            Lexeme::LCurl => braces += 1,
+            Lexeme::RCurl => braces -= 1,
+            _ => { },
+        }
+        depth += nesting(lexeme);
+        input.next();
+    }
+}
+
+pub struct Input<'a> {
+    tokens : Vec<Token>,
+    index : usize,
+    symbols : &'a mut SymbolTable,
+    fixities : HashMap<Symbol, (Assoc, u8)>,
+    // Whether errors inside of a declaration are skipped over, and the errors so far.
+    recovering : bool,
+    recovered : Vec<ParseError>,
+    // Everything that was looked for at expected_at, for error messages.
+    expected : Vec<Expected>,
+    expected_at : usize,
+    // Where errors at the end of the input are reported.
+    end : Span,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(tokens : Vec<Token>, symbols : &'a mut SymbolTable) -> Self {
+        // Doc comments aren't part of the grammar.
+        let tokens = tokens.into_iter().filter(|t| !matches!(t.lexeme, Lexeme::DocComment(_))).collect::<Vec<_>>();
+        let end = tokens.last().map_or(Span { start : 0, end : 0, line : 1, column : 1 }, |t| t.span);
+
+        let fixities = DEFAULT_FIXITIES.iter().map(|(name, assoc, precedence)| (symbols.intern(name), (*assoc, *precedence))).collect();
+
+        Input { tokens, index : 0, symbols, fixities, recovering : false, recovered : vec![], expected : vec![], expected_at : 0, end }
+    }
+
+    pub fn peek(&self) -> Option<&Lexeme> {
+        self.tokens.get(self.index).map(|t| &t.lexeme)
+    }
+
+    pub fn check(&mut self, lexeme : &Lexeme) -> bool {
+        self.expecting(Expected::Lexeme(lexeme.clone()));
+        if *lexeme == Lexeme::RAngle {
+            self.split_angle();
+        }
+        self.peek() == Some(lexeme)
+    }
+
+    // The '>>' that closes 'List<List<a>>' lexes as one operator, so when a '>' is 
+    // wanted the first character of an operator is split off into its own token.
+    fn split_angle(&mut self) {
+        let (symbol, span) = match self.tokens.get(self.index) {
+            Some(&Token { lexeme : Lexeme::Operator(symbol), span }) => (symbol, span),
+            _ => return,
+        };
+        let name = self.symbols.resolve(symbol).to_string();
+        if !name.starts_with('>') {
+            return;
+        }
+
+        let rest = lex(&name[1..], self.symbols).expect("the rest of an operator should lex");
+        let angle = Token { lexeme : Lexeme::RAngle, span : Span { end : span.start + 1, ..span } };
+        let rest = rest.into_iter().map(|t| Token { 
+            lexeme : t.lexeme, 
+            span : Span { start : span.start + 1 + t.span.start, end : span.start + 1 + t.span.end, line : span.line, column : span.column + 1 + t.span.start },
+        });
+
+        self.tokens.splice(self.index..=self.index, std::iter::once(angle).chain(rest));
+    }
+
+    pub fn expecting(&mut self, expected : Expected) {
+        if self.expected_at != self.index {
+            self.expected.clear();
+            self.expected_at = self.index;
+        }
+        if !self.expected.contains(&expected) {
+            self.expected.push(expected);
+        }
+    }
+
+    pub fn expect(&mut self, lexeme : Lexeme) -> Result<Span, ParseError> {
+        if self.check(&lexeme) {
+            let span = self.tokens[self.index].span;
+            self.index += 1;
+            Ok(span)
+        }
+        else {
+            Err(self.unexpected())
+        }
+    }
+
+    // Span of the next token.
+    pub fn span(&self) -> Span {
+        self.tokens.get(self.index).map_or(self.end, |t| t.span)
+    }
+
+    pub fn unexpected(&mut self) -> ParseError {
+        let expected = if self.expected_at == self.index { std::mem::take(&mut self.expected) } else { vec![] };
+        ParseError { expected, found : self.peek().cloned(), span : self.span() }
+    }
+
+    pub fn error(&mut self, expected : Expected) -> ParseError {
+        self.expecting(expected);
+        self.unexpected()
+    }
+
+    pub fn restore_point(&self) -> usize {
+        self.index
+    }
+
+    // Records the error and skips the rest of the item that started at start, or hands 
+    // the error back when not recovering.
+    pub fn recover(&mut self, e : ParseError, start : usize, separator : &Lexeme) -> Result<(), ParseError> {
+        if !self.recovering {
+            return Err(e);
+        }
+        self.recovered.push(e);
+        skip_item(self, start, separator);
+        Ok(())
+    }
+
+    pub fn restore(&mut self, rp : usize) {
+        self.index = rp;
+    }
+
+    // Fixity of the next token if it is an operator.  Operators without a fixity 
+    // declaration are left associative and bind tighter than any other.
+    fn fixity(&mut self) -> Option<(Assoc, u8)> {
+        let symbol = operator_symbol(self)?;
+        Some(self.fixities.get(&symbol).copied().unwrap_or((Assoc::Left, MAX_PRECEDENCE)))
+    }
+
+    // True when the next token starts right where the previous one ended, ie the '(' in 
+    // 'f(x)' but not in 'f (x)'.
+    fn adjacent(&self) -> bool {
+        match (self.index.checked_sub(1).and_then(|i| self.tokens.get(i)), self.tokens.get(self.index)) {
+            (Some(prev), Some(next)) => prev.span.end == next.span.start,
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Iterator for Input<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let r = self.tokens.get(self.index).cloned();
+        if r.is_some() {
+            self.index += 1;
+        }
+        r
+    }
+}
+
+pub struct FunParser {}
+
+impl Parser for FunParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Fun)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Fun)?;
+        let name = value_name(input)?;
+
+        let mut params = vec![];
+        while let Some(Lexeme::LowerCaseSymbol(_)) = input.peek() {
+            params.push(lower_ident(input)?);
+        }
+
+        input.expect(Lexeme::Equal)?;
+        let body = expr(input)?;
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Fun { name, params, body, public : false, span : join(start, end) })
+    }
+}
+
+pub struct LetParser {}
+
+impl Parser for LetParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Let)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Let)?;
+        let name = value_name(input)?;
+        input.expect(Lexeme::Equal)?;
+        let expr = expr(input)?;
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Let { name, expr, public : false, span : join(start, end) })
+    }
+}
+
+pub struct SpecParser {}
+
+impl Parser for SpecParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Spec)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Spec)?;
+        let name = value_name(input)?;
+        let type_params = type_params(input)?;
+        input.expect(Lexeme::Equal)?;
+        let t = parse_type(input)?;
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Spec { name, type_params, t, span : join(start, end) })
+    }
+}
+
+pub struct DataParser {}
+
+impl Parser for DataParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Data)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Data)?;
+        let name = upper_ident(input)?;
+        let type_params = type_params(input)?;
+        input.expect(Lexeme::Equal)?;
+
+        let constructors = sep_by1(rule(constructor, is_upper, Expected::UpperCaseSymbol), Lexeme::OrBar).parse(input)?;
+
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Data { name, type_params, constructors, public : false, span : join(start, end) })
+    }
+}
+
+pub struct FixityParser {}
+
+impl Parser for FixityParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        matches!( input.peek(), Some(Lexeme::Infixl | Lexeme::Infixr | Lexeme::Infix) )
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let (assoc, start) = match input.next() {
+            Some(Token { lexeme : Lexeme::Infixl, span }) => (Assoc::Left, span),
+            Some(Token { lexeme : Lexeme::Infixr, span }) => (Assoc::Right, span),
+            Some(Token { lexeme : Lexeme::Infix, span }) => (Assoc::NonAssoc, span),
+            _ => unreachable!(),
+        };
+
+        let precedence = match input.peek() {
+            Some(&Lexeme::Integer(p)) if valid_precedence(p) => { input.next(); p as u8 },
+            _ => return Err(input.error(Expected::Precedence)),
+        };
+
+        let op = operator(input).ok_or_else(|| input.error(Expected::Operator))?;
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Fixity { assoc, precedence, op, public : false, span : join(start, end) })
+    }
+}
+
+pub struct ModuleParser {}
+
+impl Parser for ModuleParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Module)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Module)?;
+        let path = module_path(input)?;
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Module { path, span : join(start, end) })
+    }
+}
+
+pub struct ImportParser {}
+
+impl Parser for ImportParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Import)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Import)?;
+        let path = module_path(input)?;
+        let names = rule(import_name, |l| is_name(l) || *l == Lexeme::LParen, Expected::Name);
+        let names = optional(tuple(names)).parse(input)?.map_or(vec![], |(ns, _)| ns);
+        let end = input.expect(Lexeme::SemiColon)?;
+
+        Ok(Ast::Import { path, names, span : join(start, end) })
+    }
+}
+
+pub struct PubParser {}
+
+impl Parser for PubParser {
+    type Output = Ast;
+
+    fn usable(&self, input : &mut Input) -> bool {
+        input.peek() == Some(&Lexeme::Pub)
+    }
+
+    fn parse(&self, input : &mut Input) -> Result<Ast, ParseError> {
+        let start = input.expect(Lexeme::Pub)?;
+
+        let mut decl = match PUBLIC.iter().find(|p| p.usable(input)) {
+            Some(parser) => parser.parse(input)?,
+            None => return Err(input.error(Expected::Declaration)),
+        };
+
+        // A public fixity changes how importers parse, which is only allowed for the 
+        // operators the module defines itself.
+        if let Ast::Fixity { op, .. } = &decl {
+            if DEFAULT_FIXITIES.iter().any(|(name, _, _)| *name == input.symbols.resolve(op.symbol)) {
+                input.index = input.tokens.iter().position(|t| t.span == op.span).unwrap();
+                return Err(input.error(Expected::UserOperator));
+            }
+        }
+
+        match &mut decl {
+            Ast::Fun { public, span, .. } 
+            | Ast::Let { public, span, .. } 
+            | Ast::Data { public, span, .. } 
+            | Ast::Fixity { public, span, .. } => {
+                *public = true;
+                *span = join(start, *span);
+            },
+            _ => unreachable!(),
+        }
+
+        Ok(decl)
+    }
+}
+
+// 'a.b.c'
+fn module_path(input : &mut Input) -> Result<Vec<Ident>, ParseError> {
+    sep_by1(names(), Lexeme::Dot).parse(input)
+}
+
+fn names() -> Rule<Ident> {
+    rule(name, is_name, Expected::Name)
+}
+
+fn name(input : &mut Input) -> Result<Ident, ParseError> {
+    match input.peek() {
+        Some(&Lexeme::LowerCaseSymbol(symbol)) | Some(&Lexeme::UpperCaseSymbol(symbol)) => 
+            Ok(Ident { symbol, span : input.next().unwrap().span }),
+        _ => Err(input.error(Expected::Name)),
+    }
+}
+
+// A name in an import list, where operators are written in brackets.
+fn import_name(input : &mut Input) -> Result<Ident, ParseError> {
+    match bracketed_operator(input) {
+        Some(op) => Ok(op),
+        None => name(input),
+    }
+}
+
+fn is_name(lexeme : &Lexeme) -> bool {
+    is_lower(lexeme) || is_upper(lexeme)
+}
+
+// 'List.map' with no spaces around the dot is the name map from the imported module 
+// List, rather than a field of the constructor List.  Consumes the dot and the name if 
+// the name is one that part accepts.
+fn qualified(input : &mut Input, qualifier : &Ident, part : fn(&Lexeme) -> bool) -> Option<(Ident, Lexeme)> {
+    let (dot, name) = match input.tokens.get(input.index..input.index + 2) {
+        Some([dot, name]) if dot.lexeme == Lexeme::Dot && part(&name.lexeme) => (dot.span, name.clone()),
+        _ => return None,
+    };
+
+    if qualifier.span.end != dot.start || dot.end != name.span.start {
+        return None;
+    }
+
+    let symbol = match name.lexeme {
+        Lexeme::LowerCaseSymbol(s) | Lexeme::UpperCaseSymbol(s) => s,
+        _ => return None,
+    };
+
+    input.next();
+    input.next();
+
+    let full = format!("{}.{}", input.symbols.resolve(qualifier.symbol), input.symbols.resolve(symbol));
+    Some((Ident { symbol : input.symbols.intern(&full), span : join(qualifier.span, name.span) }, name.lexeme))
+}
+
+// An upper case name that might be qualified, as in 'List.Cons'.
+fn upper_name(input : &mut Input) -> Result<Ident, ParseError> {
+    let name = upper_ident(input)?;
+    Ok(qualified(input, &name, is_upper).map_or(name, |(name, _)| name))
+}
+
+fn valid_precedence(precedence : i64) -> bool {
+    (0..=MAX_PRECEDENCE as i64).contains(&precedence)
+}
+
+fn operator_name(lexeme : &Lexeme) -> Option<&'static str> {
+    match lexeme {
+        Lexeme::Plus => Some("+"),
+        Lexeme::Minus => Some("-"),
+        Lexeme::Star => Some("*"),
+        Lexeme::Slash => Some("/"),
+        Lexeme::Percent => Some("%"),
+        Lexeme::DoubleEqual => Some("=="),
+        Lexeme::NotEqual => Some("!="),
+        Lexeme::LAngle => Some("<"),
+        Lexeme::RAngle => Some(">"),
+        Lexeme::LessEqual => Some("<="),
+        Lexeme::GreaterEqual => Some(">="),
+        Lexeme::DoubleAmpersand => Some("&&"),
+        Lexeme::DoubleOrBar => Some("||"),
+        Lexeme::DoubleColon => Some("::"),
+        _ => None,
+    }
+}
+
+fn operator_symbol(input : &mut Input) -> Option<Symbol> {
+    match input.peek()? {
+        &Lexeme::Operator(symbol) => Some(symbol),
+        lexeme => {
+            let name = operator_name(lexeme)?;
+            Some(input.symbols.intern(name))
+        },
+    }
+}
+
+// Consumes the next token if it is an operator.
+fn operator(input : &mut Input) -> Option<Ident> {
+    let symbol = operator_symbol(input)?;
+    Some(Ident { symbol, span : input.next().unwrap().span })
+}
+
+// '(<$>)' names a user defined operator so that it can be defined, imported or passed 
+// around like any other function.
+fn bracketed_operator(input : &mut Input) -> Option<Ident> {
+    let (open, symbol, close) = match input.tokens.get(input.index..input.index + 3) {
+        Some([open, Token { lexeme : Lexeme::Operator(symbol), .. }, close]) 
+            if open.lexeme == Lexeme::LParen && close.lexeme == Lexeme::RParen => (open.span, *symbol, close.span),
+        _ => return None,
+    };
+
+    input.index += 3;
+    Some(Ident { symbol, span : join(open, close) })
+}
+
+// The name of a function or value, which is either a lower case symbol or an operator.
+fn value_name(input : &mut Input) -> Result<Ident, ParseError> {
+    match bracketed_operator(input) {
+        Some(op) => Ok(op),
+        None => lower_ident(input),
+    }
+}
+
+fn constructor(input : &mut Input) -> Result<Constructor, ParseError> {
+    let name = upper_ident(input)?;
+
+    if input.check(&Lexeme::LCurl) {
+        let fields = rule(field_type, is_lower, Expected::LowerCaseSymbol);
+        let (fields, span) = delimited(Lexeme::LCurl, sep_by1(fields, Lexeme::Comma), Lexeme::RCurl).parse(input)?;
+        let (field_names, fields) = fields.into_iter().unzip();
+        return Ok(Constructor { span : join(name.span, span), name, fields, field_names });
+    }
+
+    match optional(tuple(types())).parse(input)? {
+        Some((fields, span)) => Ok(Constructor { span : join(name.span, span), name, fields, field_names : vec![] }),
+        None => Ok(Constructor { span : name.span, name, fields : vec![], field_names : vec![] }),
+    }
+}
+
+// 'name : Type' in a record constructor.
+fn field_type(input : &mut Input) -> Result<(Ident, Type), ParseError> {
+    let name = lower_ident(input)?;
+    input.expect(Lexeme::Colon)?;
+    let t = parse_type(input)?;
+    Ok((name, t))
+}
+
+// 'name = expr' in a record expression or update.
+fn field_value(input : &mut Input) -> Result<FieldValue, ParseError> {
+    let name = lower_ident(input)?;
+    input.expect(Lexeme::Equal)?;
+    let e = expr(input)?;
+    Ok(FieldValue { span : join(name.span, e.span), name, expr : e })
+}
+
+fn field_values() -> SepBy<Rule<FieldValue>> {
+    sep_by1(rule(field_value, is_lower, Expected::LowerCaseSymbol), Lexeme::Comma)
+}
+
+// 'name = pattern', or just 'name' which binds the field to a variable of the same name.
+fn field_pattern(input : &mut Input) -> Result<FieldPattern, ParseError> {
+    let name = lower_ident(input)?;
+
+    if input.check(&Lexeme::Equal) {
+        input.next();
+        let p = pattern(input)?;
+        Ok(FieldPattern { span : join(name.span, p.span), name, pattern : p })
+    }
+    else {
+        let p = Pattern { kind : PatternKind::Var(name.symbol), span : name.span };
+        Ok(FieldPattern { span : name.span, name, pattern : p })
+    }
+}
+
+fn type_params(input : &mut Input) -> Result<Vec<Ident>, ParseError> {
+    let params = rule(lower_ident, is_lower, Expected::LowerCaseSymbol);
+    Ok(optional(angled(params)).parse(input)?.map_or(vec![], |(ps, _)| ps))
+}
+
+fn is_lower(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::LowerCaseSymbol(_) )
+}
+
+fn is_upper(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::UpperCaseSymbol(_) )
+}
+
+// '(item, item, ...)'
+fn tuple<T>(item : Rule<T>) -> Delimited<SepBy<Rule<T>>> {
+    delimited(Lexeme::LParen, sep_by(item, Lexeme::Comma), Lexeme::RParen)
+}
+
+// True when the tuple that was just parsed ended with ',)', which makes '(x,)' a tuple 
+// of one rather than grouping.
+fn trailing_comma(input : &Input) -> bool {
+    input.index >= 2 && input.tokens[input.index - 2].lexeme == Lexeme::Comma
+}
+
+// '<item, item, ...>'
+fn angled<T>(item : Rule<T>) -> Delimited<SepBy<Rule<T>>> {
+    delimited(Lexeme::LAngle, sep_by(item, Lexeme::Comma), Lexeme::RAngle)
+}
+
+fn types() -> Rule<Type> {
+    rule(parse_type, starts_type, Expected::Type)
+}
+
+fn exprs() -> Rule<Expr> {
+    rule(expr, starts_expr, Expected::Expr)
+}
+
+fn patterns() -> Rule<Pattern> {
+    rule(pattern, starts_atomic_pattern, Expected::Pattern)
+}
+
+fn lower_ident(input : &mut Input) -> Result<Ident, ParseError> {
+    match input.peek() {
+        Some(&Lexeme::LowerCaseSymbol(symbol)) => Ok(Ident { symbol, span : input.next().unwrap().span }),
+        _ => Err(input.error(Expected::LowerCaseSymbol)),
+    }
+}
+
+fn upper_ident(input : &mut Input) -> Result<Ident, ParseError> {
+    match input.peek() {
+        Some(&Lexeme::UpperCaseSymbol(symbol)) => Ok(Ident { symbol, span : input.next().unwrap().span }),
+        _ => Err(input.error(Expected::UpperCaseSymbol)),
+    }
+}
+
+fn join(start : Span, end : Span) -> Span {
+    Span { start : start.start, end : end.end, line : start.line, column : start.column }
+}
+
+fn parse_type(input : &mut Input) -> Result<Type, ParseError> {
+    let src = type_primary(input)?;
+
+    if input.check(&Lexeme::RightArrow) {
+        input.next();
+        let dest = parse_type(input)?;
+        let span = join(src.span, dest.span);
+        Ok(Type { kind : TypeKind::Arrow { src : Box::new(src), dest : Box::new(dest) }, span })
+    }
+    else {
+        Ok(src)
+    }
+}
+
+fn starts_type(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::LowerCaseSymbol(_) | Lexeme::UpperCaseSymbol(_) | Lexeme::LParen )
+}
+
+fn type_primary(input : &mut Input) -> Result<Type, ParseError> {
+    match input.peek() {
+        Some(Lexeme::LowerCaseSymbol(_)) => {
+            let name = lower_ident(input)?;
+            Ok(Type { kind : TypeKind::Generic(name.symbol), span : name.span })
+        },
+        Some(Lexeme::UpperCaseSymbol(_)) => {
+            let name = upper_name(input)?;
+            match optional(angled(types())).parse(input)? {
+                Some((args, span)) => Ok(Type { kind : TypeKind::Applied { name : name.symbol, args }, span : join(name.span, span) }),
+                None => Ok(Type { kind : TypeKind::Concrete(name.symbol), span : name.span }),
+            }
+        },
+        Some(Lexeme::LParen) => {
+            let (mut ts, span) = tuple(types()).parse(input)?;
+            // A single type in parens is just grouping.
+            if ts.len() == 1 && !trailing_comma(input) {
+                Ok(ts.remove(0))
+            }
+            else {
+                Ok(Type { kind : TypeKind::Tuple(ts), span })
+            }
+        },
+        _ => Err(input.error(Expected::Type)),
+    }
+}
+
+fn expr(input : &mut Input) -> Result<Expr, ParseError> {
+    binary(input, 0)
+}
+
+// Precedence climbing over the fixity table.  Operators that bind looser than 
+// min_precedence are left for a caller further up.
+fn binary(input : &mut Input, min_precedence : u8) -> Result<Expr, ParseError> {
+    let mut lhs = unary(input)?;
+
+    loop {
+        let (assoc, precedence) = match input.fixity() {
+            Some((assoc, precedence)) if precedence >= min_precedence => (assoc, precedence),
+            _ => break,
+        };
+
+        let op = operator(input).unwrap();
+        let next_precedence = match assoc {
+            Assoc::Right => precedence,
+            Assoc::Left | Assoc::NonAssoc => precedence + 1,
+        };
+        let rhs = binary(input, next_precedence)?;
+
+        let span = join(lhs.span, rhs.span);
+        lhs = Expr { kind : ExprKind::Binary { op, lhs : Box::new(lhs), rhs : Box::new(rhs) }, span };
+
+        // 'a == b == c' is an error rather than picking a grouping.
+        if assoc == Assoc::NonAssoc && matches!( input.fixity(), Some((_, p)) if p == precedence ) {
+            return Err(ParseError { expected : vec![Expected::NonAssociative], found : input.peek().cloned(), span : input.span() });
+        }
+    }
+
+    Ok(lhs)
+}
+
+// Prefix operators bind looser than application, so '-f x' is '-(f x)'.
+fn unary(input : &mut Input) -> Result<Expr, ParseError> {
+    let op = match input.peek() {
+        Some(Lexeme::Minus) => Some("-"),
+        Some(Lexeme::Bang) => Some("!"),
+        _ => None,
+    };
+
+    if let Some(op) = op {
+        let op = Ident { symbol : input.symbols.intern(op), span : input.next().unwrap().span };
+        let e = unary(input)?;
+        let span = join(op.span, e.span);
+        Ok(Expr { kind : ExprKind::Unary { op, expr : Box::new(e) }, span })
+    }
+    else {
+        application(input)
+    }
+}
+
+// 'f x y' applies f to two arguments.  When a constructor is applied the arguments
+// become the constructor's.
+fn application(input : &mut Input) -> Result<Expr, ParseError> {
+    let head = postfix(input)?;
+
+    let mut args = vec![];
+    while input.peek().is_some_and(starts_argument) {
+        args.push(postfix(input)?);
+    }
+
+    if args.is_empty() {
+        return Ok(head);
+    }
+
+    let span = join(head.span, args.last().unwrap().span);
+    match head.kind {
+        ExprKind::Constructor { name, args : ref existing } if existing.is_empty() => 
+            Ok(Expr { kind : ExprKind::Constructor { name, args }, span }),
+        _ => Ok(Expr { kind : ExprKind::Call { fun : Box::new(head), args }, span }),
+    }
+}
+
+// Braces and match don't start arguments so that 'match f x { ... }' works.
+fn starts_argument(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::Bool(_)
+                    | Lexeme::Integer(_)
+                    | Lexeme::Decimal(_)
+                    | Lexeme::Char(_)
+                    | Lexeme::String(_)
+                    | Lexeme::InterpolatedString(_)
+                    | Lexeme::LowerCaseSymbol(_)
+                    | Lexeme::UpperCaseSymbol(_)
+                    | Lexeme::LParen
+                    | Lexeme::LSquare
+                    )
+}
+
+fn starts_expr(lexeme : &Lexeme) -> bool {
+    starts_argument(lexeme) || matches!( lexeme, Lexeme::Minus | Lexeme::Bang | Lexeme::Match | Lexeme::LCurl | Lexeme::Backslash | Lexeme::If )
+}
+
+// A parenthesized argument list directly after an expression, as in 'f(x, y)' or
+// 'Some(1)', passes each item as a separate argument.  Field access 'p.name' binds 
+// just as tightly, so 'f p.name' is 'f (p.name)'.
+fn postfix(input : &mut Input) -> Result<Expr, ParseError> {
+    let mut e = primary(input)?;
+
+    loop {
+        if input.check(&Lexeme::Dot) {
+            input.next();
+            let name = lower_ident(input)?;
+            let span = join(e.span, name.span);
+            e = Expr { kind : ExprKind::Field { expr : Box::new(e), name }, span };
+        }
+        else if input.check(&Lexeme::LParen) && input.adjacent() {
+            let (args, span) = tuple(exprs()).parse(input)?;
+            let span = join(e.span, span);
+            e = match e.kind {
+                ExprKind::Constructor { name, args : existing } if existing.is_empty() => 
+                    Expr { kind : ExprKind::Constructor { name, args }, span },
+                kind => {
+                    let fun = Expr { kind, span : e.span };
+                    Expr { kind : ExprKind::Call { fun : Box::new(fun), args }, span }
+                },
+            };
+        }
+        else {
+            return Ok(e);
+        }
+    }
+}
+
+// 'Person { name = ...' rather than a constructor followed by a block, as in 
+// 'match None { ... }'.  A block can't start with 'name =', so the two tokens after 
+// the brace tell them apart.  The constructor may be qualified, as in 'Mod.Person'.
+fn starts_record(input : &Input) -> bool {
+    let brace = match input.tokens.get(input.index..input.index + 3) {
+        Some([ Token { lexeme : Lexeme::UpperCaseSymbol(_), span : qualifier }
+             , Token { lexeme : Lexeme::Dot, span : dot }
+             , Token { lexeme : Lexeme::UpperCaseSymbol(_), span : name }
+             ]) if qualifier.end == dot.start && dot.end == name.start => input.index + 3,
+        _ => input.index + 1,
+    };
+
+    matches!( input.tokens.get(brace - 1..brace + 3)
+            , Some([ Token { lexeme : Lexeme::UpperCaseSymbol(_), .. }
+                   , Token { lexeme : Lexeme::LCurl, .. }
+                   , Token { lexeme : Lexeme::LowerCaseSymbol(_), .. }
+                   , Token { lexeme : Lexeme::Equal, .. }
+                   ])
+            )
+}
+
+fn record(input : &mut Input) -> Result<Expr, ParseError> {
+    let name = upper_name(input)?;
+    let (fields, span) = delimited(Lexeme::LCurl, field_values(), Lexeme::RCurl).parse(input)?;
+    Ok(Expr { kind : ExprKind::Record { name : name.symbol, fields }, span : join(name.span, span) })
+}
+
+fn primary(input : &mut Input) -> Result<Expr, ParseError> {
+    let literal = |kind, input : &mut Input| Ok(Expr { kind, span : input.next().unwrap().span });
+
+    match input.peek() {
+        Some(&Lexeme::Bool(b)) => literal(ExprKind::Bool(b), input),
+        Some(&Lexeme::Integer(i)) => literal(ExprKind::Integer(i), input),
+        Some(&Lexeme::Decimal(d)) => literal(ExprKind::Decimal(d), input),
+        Some(&Lexeme::Char(c)) => literal(ExprKind::Char(c), input),
+        Some(Lexeme::String(s)) => {
+            let s = s.clone();
+            literal(ExprKind::String(s), input)
+        },
+        Some(Lexeme::InterpolatedString(_)) => interpolated_string(input),
+        Some(&Lexeme::LowerCaseSymbol(symbol)) => literal(ExprKind::Var(symbol), input),
+        Some(Lexeme::UpperCaseSymbol(_)) if starts_record(input) => record(input),
+        Some(Lexeme::UpperCaseSymbol(_)) => {
+            let name = upper_ident(input)?;
+            let (name, var) = match qualified(input, &name, is_name) {
+                Some((name, lexeme)) => (name, is_lower(&lexeme)),
+                None => (name, false),
+            };
+            let kind = if var { ExprKind::Var(name.symbol) } else { ExprKind::Constructor { name : name.symbol, args : vec![] } };
+            Ok(Expr { kind, span : name.span })
+        },
+        Some(Lexeme::LParen) => {
+            if let Some(op) = bracketed_operator(input) {
+                return Ok(Expr { kind : ExprKind::Var(op.symbol), span : op.span });
+            }
+
+            let (mut es, span) = tuple(exprs()).parse(input)?;
+            // A single expression in parens is just grouping.
+            if es.len() == 1 && !trailing_comma(input) {
+                Ok(es.remove(0))
+            }
+            else {
+                Ok(Expr { kind : ExprKind::Tuple(es), span })
+            }
+        },
+        Some(Lexeme::LSquare) => {
+            let (es, span) = delimited(Lexeme::LSquare, sep_by(exprs(), Lexeme::Comma), Lexeme::RSquare).parse(input)?;
+            Ok(Expr { kind : ExprKind::List(es), span })
+        },
+        Some(Lexeme::Match) => match_expr(input),
+        Some(Lexeme::LCurl) => block(input),
+        Some(Lexeme::Backslash) => lambda(input),
+        Some(Lexeme::If) => if_expr(input),
+        _ => Err(input.error(Expected::Expr)),
+    }
+}
+
+// '\x y => body'.  The body extends as far to the right as it can.
+fn lambda(input : &mut Input) -> Result<Expr, ParseError> {
+    let start = input.expect(Lexeme::Backslash)?;
+
+    let mut params = vec![lower_ident(input)?];
+    while input.peek().is_some_and(is_lower) {
+        params.push(lower_ident(input)?);
+    }
+
+    input.expect(Lexeme::RightDoubleArrow)?;
+    let body = expr(input)?;
+    let span = join(start, body.span);
+
+    Ok(Expr { kind : ExprKind::Lambda { params, body : Box::new(body), captures : vec![] }, span })
+}
+
+// 'if c { a } else if d { b } else { c }'.  The else is required because if is an 
+// expression.
+fn if_expr(input : &mut Input) -> Result<Expr, ParseError> {
+    let start = input.expect(Lexeme::If)?;
+    let cond = expr(input)?;
+    let then = block(input)?;
+    input.expect(Lexeme::Else)?;
+
+    let otherwise = if input.check(&Lexeme::If) {
+        if_expr(input)?
+    }
+    else {
+        block(input)?
+    };
+
+    let span = join(start, otherwise.span);
+    Ok(Expr { kind : ExprKind::If { cond : Box::new(cond), then : Box::new(then), otherwise : Box::new(otherwise) }, span })
+}
+
+fn match_expr(input : &mut Input) -> Result<Expr, ParseError> {
+    let start = input.expect(Lexeme::Match)?;
+    let scrutinee = expr(input)?;
+
+    let arms = recover(rule(match_arm, starts_atomic_pattern, Expected::Pattern), Lexeme::Comma);
+    let (arms, span) = delimited(Lexeme::LCurl, sep_by(arms, Lexeme::Comma), Lexeme::RCurl).parse(input)?;
+    let arms = arms.into_iter().flatten().collect();
+
+    Ok(Expr { kind : ExprKind::Match { expr : Box::new(scrutinee), arms }, span : join(start, span) })
+}
+
+fn match_arm(input : &mut Input) -> Result<MatchArm, ParseError> {
+    let pattern = pattern(input)?;
+
+    let guard = if input.check(&Lexeme::If) {
+        input.next();
+        Some(expr(input)?)
+    }
+    else {
+        None
+    };
+
+    input.expect(Lexeme::RightDoubleArrow)?;
+    let e = expr(input)?;
+
+    Ok(MatchArm { span : join(pattern.span, e.span), pattern, guard, expr : e })
+}
+
+fn block(input : &mut Input) -> Result<Expr, ParseError> {
+    let start = input.expect(Lexeme::LCurl)?;
+
+    let mut statements = vec![];
+    while let Some(parser) = STATEMENTS.iter().find(|p| p.usable(input)) {
+        match recover(parser, Lexeme::SemiColon).parse(input)? {
+            Some(statement) => statements.push(statement),
+            // The skipping stops before the ';' of the broken statement.
+            None => if input.peek() == Some(&Lexeme::SemiColon) { input.next(); },
+        }
+    }
+
+    let e = expr(input)?;
+
+    // '{ p with age = 3 }' is a record update rather than a block.
+    if statements.is_empty() && input.check(&Lexeme::With) {
+        input.next();
+        let fields = field_values().parse(input)?;
+        let end = input.expect(Lexeme::RCurl)?;
+        return Ok(Expr { kind : ExprKind::Update { expr : Box::new(e), fields }, span : join(start, end) });
+    }
+
+    let end = input.expect(Lexeme::RCurl)?;
+
+    Ok(Expr { kind : ExprKind::Block { statements, expr : Box::new(e) }, span : join(start, end) })
+}
+
+// Or-patterns bind loosest, so 'x @ A | B' is '(x @ A) | B'.
+fn pattern(input : &mut Input) -> Result<Pattern, ParseError> {
+    let first = as_pattern(input)?;
+
+    if !input.check(&Lexeme::OrBar) {
+        return Ok(first);
+    }
+
+    let mut ps = vec![first];
+    while input.check(&Lexeme::OrBar) {
+        input.next();
+        ps.push(as_pattern(input)?);
+    }
+
+    let span = join(ps[0].span, ps.last().unwrap().span);
+    Ok(Pattern { kind : PatternKind::Or(ps), span })
+}
+
+fn as_pattern(input : &mut Input) -> Result<Pattern, ParseError> {
+    let named = matches!( input.peek(), Some(Lexeme::LowerCaseSymbol(_)) ) 
+             && matches!( input.tokens.get(input.index + 1), Some(Token { lexeme : Lexeme::At, .. }) );
+
+    if named {
+        let name = lower_ident(input)?;
+        input.expect(Lexeme::At)?;
+        let p = constructor_pattern(input)?;
+        let span = join(name.span, p.span);
+        Ok(Pattern { kind : PatternKind::As { name, pattern : Box::new(p) }, span })
+    }
+    else {
+        constructor_pattern(input)
+    }
+}
+
+// Constructor patterns take their arguments the same way constructor expressions do: 
+// 'Cons x xs', 'Pair(a, b)'.
+fn constructor_pattern(input : &mut Input) -> Result<Pattern, ParseError> {
+    let name = match input.peek() {
+        Some(Lexeme::UpperCaseSymbol(_)) => upper_name(input)?,
+        _ => return atomic_pattern(input),
+    };
+
+    if input.check(&Lexeme::LCurl) {
+        let fields = rule(field_pattern, is_lower, Expected::LowerCaseSymbol);
+        let (fields, span) = delimited(Lexeme::LCurl, sep_by1(fields, Lexeme::Comma), Lexeme::RCurl).parse(input)?;
+        return Ok(Pattern { kind : PatternKind::Record { name : name.symbol, fields }, span : join(name.span, span) });
+    }
+
+    let (args, span) = if input.check(&Lexeme::LParen) && input.adjacent() {
+        let (args, span) = tuple(patterns()).parse(input)?;
+        (args, join(name.span, span))
+    }
+    else {
+        let mut args = vec![];
+        while input.peek().is_some_and(starts_atomic_pattern) {
+            args.push(atomic_pattern(input)?);
+        }
+        let span = args.last().map_or(name.span, |a| join(name.span, a.span));
+        (args, span)
+    };
+
+    Ok(Pattern { kind : PatternKind::Constructor { name : name.symbol, args }, span })
+}
+
+fn starts_atomic_pattern(lexeme : &Lexeme) -> bool {
+    matches!( lexeme, Lexeme::Underscore
+                    | Lexeme::Bool(_)
+                    | Lexeme::Integer(_)
+                    | Lexeme::Decimal(_)
+                    | Lexeme::Char(_)
+                    | Lexeme::String(_)
+                    | Lexeme::LowerCaseSymbol(_)
+                    | Lexeme::UpperCaseSymbol(_)
+                    | Lexeme::LParen
+                    | Lexeme::LSquare
+                    )
+}
+
+fn atomic_pattern(input : &mut Input) -> Result<Pattern, ParseError> {
+    let literal = |kind, input : &mut Input| Ok(Pattern { kind, span : input.next().unwrap().span });
+
+    match input.peek() {
+        Some(Lexeme::Underscore) => literal(PatternKind::Wildcard, input),
+        Some(&Lexeme::Bool(b)) => literal(PatternKind::Bool(b), input),
+        Some(&Lexeme::Integer(i)) => literal(PatternKind::Integer(i), input),
+        Some(&Lexeme::Decimal(d)) => literal(PatternKind::Decimal(d), input),
+        Some(&Lexeme::Char(c)) => literal(PatternKind::Char(c), input),
+        Some(Lexeme::String(s)) => {
+            let s = s.clone();
+            literal(PatternKind::String(s), input)
+        },
+        Some(&Lexeme::LowerCaseSymbol(symbol)) => literal(PatternKind::Var(symbol), input),
+        // Arguments of a constructor pattern that are themselves constructors need parens 
+        // unless they take no arguments, as in 'Pair None x'.
+        Some(Lexeme::UpperCaseSymbol(_)) => {
+            let name = upper_name(input)?;
+            Ok(Pattern { kind : PatternKind::Constructor { name : name.symbol, args : vec![] }, span : name.span })
+        },
+        Some(Lexeme::LParen) => {
+            let (mut ps, span) = tuple(patterns()).parse(input)?;
+            if ps.len() == 1 && !trailing_comma(input) {
+                Ok(ps.remove(0))
+            }
+            else {
+                Ok(Pattern { kind : PatternKind::Tuple(ps), span })
+            }
+        },
+        Some(Lexeme::LSquare) => list_pattern(input),
+        _ => Err(input.error(Expected::Pattern)),
+    }
+}
+
+// '[a, b]', or '[a, b, ..rest]' to match lists of at least two.  A bare '..' ignores 
+// the rest of the list.
+fn list_pattern(input : &mut Input) -> Result<Pattern, ParseError> {
+    let start = input.expect(Lexeme::LSquare)?;
+
+    let mut items = vec![];
+    let mut rest = None;
+    while !input.check(&Lexeme::RSquare) {
+        if input.check(&Lexeme::DotDot) {
+            let dots = input.next().unwrap().span;
+            rest = Some(match input.peek() {
+                Some(Lexeme::LowerCaseSymbol(_)) => {
+                    let name = lower_ident(input)?;
+                    Pattern { kind : PatternKind::Var(name.symbol), span : name.span }
+                },
+                _ => Pattern { kind : PatternKind::Wildcard, span : dots },
+            });
+            break;
+        }
+
+        items.push(pattern(input)?);
+
+        if !input.check(&Lexeme::Comma) {
+            break;
+        }
+        input.next();
+    }
+
+    let end = input.expect(Lexeme::RSquare)?;
+    Ok(Pattern { kind : PatternKind::List { items, rest : rest.map(Box::new) }, span : join(start, end) })
+}
+
+// The code in each interpolation is parsed on its own and has to be exactly one 
+// expression.  Desugaring turns the parts into calls to the prelude's concat.
+fn interpolated_string(input : &mut Input) -> Result<Expr, ParseError> {
+    let token = input.next().unwrap();
+    let span = token.span;
+    let parts = match token.lexeme {
+        Lexeme::InterpolatedString(parts) => parts,
+        _ => unreachable!(),
+    };
+
+    let mut es = vec![];
+    for part in parts {
+        match part {
+            StringPart::Literal(l) if l.is_empty() => { },
+            StringPart::Literal(l) => es.push(Expr { kind : ExprKind::String(l), span }),
+            StringPart::Code(tokens) => {
+                let mut code = Input::new(tokens, input.symbols);
+                code.fixities = input.fixities.clone();
+                code.end = span;
+
+                let e = expr(&mut code)?;
+                if code.peek().is_some() {
+                    return Err(code.unexpected());
+                }
+                es.push(e);
+            },
+        }
+    }
+
+    Ok(Expr { kind : ExprKind::Interpolated(es), span })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lex::lex;
+
+    fn parse(s : &str, symbols : &mut SymbolTable) -> Result<Vec<Ast>, ParseError> {
+        let tokens = lex(s, symbols).expect("lex should succeed");
+        parse_module(tokens, symbols)
+    }
+
+    fn var(symbols : &mut SymbolTable, s : &str) -> ExprKind { ExprKind::Var(symbols.intern(s)) }
+
+    #[test]
+    fn parse_module_should_parse_fun() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("fun add x y = plus(x, y);", &mut symbols).expect("parse should succeed");
+
+        assert_eq!( r.len(), 1 );
+        let (name, params, body, span) = match &r[0] {
+            Ast::Fun { name, params, body, span, .. } => (name, params, body, span),
+            x => panic!("expected fun but found {:?}", x),
+        };
+
+        assert_eq!( name.symbol, symbols.intern("add") );
+        assert_eq!( params.iter().map(|p| p.symbol).collect::<Vec<_>>(), vec![symbols.intern("x"), symbols.intern("y")] );
+        assert_eq!( *span, Span { start : 0, end : 25, line : 1, column : 1 } );
+
+        let (fun, args) = match &body.kind {
+            ExprKind::Call { fun, args } => (fun, args),
+            x => panic!("expected call but found {:?}", x),
+        };
+
+        assert_eq!( fun.kind, var(&mut symbols, "plus") );
+        assert_eq!( args.iter().map(|a| a.kind.clone()).collect::<Vec<_>>(), vec![var(&mut symbols, "x"), var(&mut symbols, "y")] );
+        assert_eq!( body.span, Span { start : 14, end : 24, line : 1, column : 15 } );
+    }
+
+    #[test]
+    fn parse_module_should_parse_let_with_literals_and_tuples() {
+        let table = vec![ ("let x = 5;", ExprKind::Integer(5))
+                        , ("let x = 1.5;", ExprKind::Decimal(1.5))
+                        , ("let x = true;", ExprKind::Bool(true))
+                        , ("let x = 'c';", ExprKind::Char('c'))
+                        , ("let x = \"s\";", ExprKind::String("s".to_string()))
+                        , ("let x = (5);", ExprKind::Integer(5))
+                        , ("let x = ();", ExprKind::Tuple(vec![]))
+                        , ("let x = (5,);", ExprKind::Tuple(vec![Expr { kind : ExprKind::Integer(5), span : Span { start : 9, end : 10, line : 1, column : 10 } }]))
+                        ];
+
+        for (s, expected) in table {
+            let mut symbols = SymbolTable::new();
+
+            let r = parse(s, &mut symbols).expect("parse should succeed");
+
+            assert!( matches!( &r[0], Ast::Let { expr, .. } if expr.kind == expected ), "{}", s );
+        }
+    }
+
+    #[test]
+    fn parse_module_should_parse_tuples_of_one() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("spec f = (a,) -> (a); fun g x = match x { (y,) => y, (z) => z };", &mut symbols).expect("parse should succeed");
+
+        assert!( matches!( &r[0], Ast::Spec { t : Type { kind : TypeKind::Arrow { src, dest }, .. }, .. } 
+                                  if matches!( &src.kind, TypeKind::Tuple(ts) if ts.len() == 1 ) 
+                                  && matches!( dest.kind, TypeKind::Generic(_) ) ) );
+
+        let arms = match &r[1] {
+            Ast::Fun { body : Expr { kind : ExprKind::Match { arms, .. }, .. }, .. } => arms,
+            x => panic!("expected match but found {:?}", x),
+        };
+
+        assert!( matches!( &arms[0].pattern.kind, PatternKind::Tuple(ps) if ps.len() == 1 ) );
+        assert!( matches!( arms[1].pattern.kind, PatternKind::Var(_) ) );
+    }
+
+    #[test]
+    fn parse_module_should_parse_tuples_and_constructors() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("let x = (1, Some(2), None,);", &mut symbols).expect("parse should succeed");
+
+        let es = match &r[0] {
+            Ast::Let { expr : Expr { kind : ExprKind::Tuple(es), .. }, .. } => es,
+            x => panic!("expected tuple but found {:?}", x),
+        };
+
+        assert_eq!( es.len(), 3 );
+        assert_eq!( es[1].kind, ExprKind::Constructor { name : symbols.intern("Some")
+                                                      , args : vec![Expr { kind : ExprKind::Integer(2), span : Span { start : 17, end : 18, line : 1, column : 18 } }] 
+                                                      } );
+        assert_eq!( es[2].kind, ExprKind::Constructor { name : symbols.intern("None"), args : vec![] } );
+    }
+
+    #[test]
+    fn parse_module_should_parse_spec() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("spec map<a, b> = (a -> b) -> List<a> -> (List<b>, Int);", &mut symbols).expect("parse should succeed");
+
+        let (type_params, t) = match &r[0] {
+            Ast::Spec { type_params, t, .. } => (type_params, t),
+            x => panic!("expected spec but found {:?}", x),
+        };
+
+        assert_eq!( type_params.len(), 2 );
+
+        let strip = |t : &Type| format!("{:?}", t.kind);
+        let (src, dest) = match &t.kind {
+            TypeKind::Arrow { src, dest } => (src, dest),
+            x => panic!("expected arrow but found {:?}", x),
+        };
+
+        assert!( matches!( src.kind, TypeKind::Arrow { .. } ) );
+        assert_eq!( src.span, Span { start : 18, end : 24, line : 1, column : 19 } );
+
+        let (src, dest) = match &dest.kind {
+            TypeKind::Arrow { src, dest } => (src, dest),
+            x => panic!("expected arrow but found {:?}", x),
+        };
+
+        assert!( matches!( &src.kind, TypeKind::Applied { args, .. } if args.len() == 1 ), "{}", strip(src) );
+        assert!( matches!( &dest.kind, TypeKind::Tuple(ts) if ts.len() == 2 ), "{}", strip(dest) );
+    }
+
+    #[test]
+    fn parse_module_should_parse_data() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("data Option<a> = Some(a) | None;", &mut symbols).expect("parse should succeed");
+
+        let (name, type_params, constructors) = match &r[0] {
+            Ast::Data { name, type_params, constructors, .. } => (name, type_params, constructors),
+            x => panic!("expected data but found {:?}", x),
+        };
+
+        assert_eq!( name.symbol, symbols.intern("Option") );
+        assert_eq!( type_params[0].symbol, symbols.intern("a") );
+        assert_eq!( constructors.len(), 2 );
+        assert_eq!( constructors[0].name.symbol, symbols.intern("Some") );
+        assert_eq!( constructors[0].fields[0].kind, TypeKind::Generic(symbols.intern("a")) );
+        assert_eq!( constructors[0].span, Span { start : 17, end : 24, line : 1, column : 18 } );
+        assert!( constructors[1].fields.is_empty() );
+    }
+
+    #[test]
+    fn parse_module_should_parse_module_and_imports() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("module app.main; import collections.List; import std.io (print, Handle);", &mut symbols).expect("parse should succeed");
+
+        let path = |p : &Vec<Ident>| p.iter().map(|i| i.symbol).collect::<Vec<_>>();
+
+        match &r[0] {
+            Ast::Module { path : p, span } => {
+                assert_eq!( path(p), vec![symbols.intern("app"), symbols.intern("main")] );
+                assert_eq!( *span, Span { start : 0, end : 16, line : 1, column : 1 } );
+            },
+            x => panic!("expected module but found {:?}", x),
+        }
+        match &r[1] {
+            Ast::Import { path : p, names, .. } => {
+                assert_eq!( path(p), vec![symbols.intern("collections"), symbols.intern("List")] );
+                assert!( names.is_empty() );
+            },
+            x => panic!("expected import but found {:?}", x),
+        }
+        match &r[2] {
+            Ast::Import { path : p, names, .. } => {
+                assert_eq!( path(p), vec![symbols.intern("std"), symbols.intern("io")] );
+                assert_eq!( path(names), vec![symbols.intern("print"), symbols.intern("Handle")] );
+            },
+            x => panic!("expected import but found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn parse_module_should_parse_pub() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("pub fun f x = x; let y = 1; pub let z = 2; pub data D = D; data E = E;", &mut symbols).expect("parse should succeed");
+
+        let public = r.iter().map(|d| match d {
+            Ast::Fun { public, .. } | Ast::Let { public, .. } | Ast::Data { public, .. } => *public,
+            x => panic!("expected fun, let or data but found {:?}", x),
+        }).collect::<Vec<_>>();
+
+        assert_eq!( public, vec![true, false, true, true, false] );
+        assert_eq!( r[0].span(), Span { start : 0, end : 16, line : 1, column : 1 } );
+    }
+
+    #[test]
+    fn parse_module_should_parse_qualified_names() {
+        let table = vec![ ("let x = List.map f xs;", "List.map(f, xs)")
+                        , ("let x = List.Cons 1 List.Nil;", "List.Cons(1, List.Nil)")
+                        , ("let x = f(List.empty).length;", "f(List.empty).length")
+                        ];
+
+        for (s, expected) in table {
+            assert_eq!( parse_expr(s), expected, "{}", s );
+        }
+
+        let mut symbols = SymbolTable::new();
+        let table = vec![ ("let x = List.map;", ExprKind::Var(symbols.intern("List.map")))
+                        , ("let x = List.Nil;", ExprKind::Constructor { name : symbols.intern("List.Nil"), args : vec![] })
+                        ];
+
+        for (s, expected) in table {
+            let r = parse(s, &mut symbols).expect("parse should succeed");
+
+            assert!( matches!( &r[0], Ast::Let { expr, .. } if expr.kind == expected ), "{}", s );
+        }
+
+        // With spaces it's a field of a constructor.
+        let r = parse("let x = List . map;", &mut symbols).expect("parse should succeed");
+
+        assert!( matches!( &r[0], Ast::Let { expr : Expr { kind : ExprKind::Field { .. }, .. }, .. } ) );
+
+        let r = parse("spec f = Map.Map<Int, a> -> a;", &mut symbols).expect("parse should succeed");
+
+        assert!( matches!( &r[0], Ast::Spec { t : Type { kind : TypeKind::Arrow { src, .. }, .. }, .. } 
+                                  if matches!( src.kind, TypeKind::Applied { name, .. } if name == symbols.intern("Map.Map") ) ) );
+    }
+
+    #[test]
+    fn parse_module_should_parse_record_data() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("data Person = Person { name : String, age : Int } | Nobody;", &mut symbols).expect("parse should succeed");
+
+        let constructors = match &r[0] {
+            Ast::Data { constructors, .. } => constructors,
+            x => panic!("expected data but found {:?}", x),
+        };
+
+        assert_eq!( constructors[0].field_names.iter().map(|f| f.symbol).collect::<Vec<_>>(), vec![symbols.intern("name"), symbols.intern("age")] );
+        assert_eq!( constructors[0].fields.iter().map(|f| f.kind.clone()).collect::<Vec<_>>()
+                  , vec![TypeKind::Concrete(symbols.intern("String")), TypeKind::Concrete(symbols.intern("Int"))] 
+                  );
+        assert_eq!( constructors[0].span, Span { start : 14, end : 49, line : 1, column : 15 } );
+        assert!( constructors[1].field_names.is_empty() );
+    }
+
+    #[test]
+    fn parse_module_should_not_take_match_body_as_record() {
+        let mut symbols = SymbolTable::new();
+
+        let arms = parse_arms("let x = match None { None => 0 };", &mut symbols);
+
+        assert_eq!( arms.len(), 1 );
+    }
+
+    #[test]
+    fn parse_module_should_parse_match_and_block() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("fun f x = match x { Some((a, b)) => { let c = a; c }, None => 0, };", &mut symbols).expect("parse should succeed");
+
+        let (e, arms) = match &r[0] {
+            Ast::Fun { body : Expr { kind : ExprKind::Match { expr, arms }, .. }, .. } => (expr, arms),
+            x => panic!("expected match but found {:?}", x),
+        };
+
+        assert_eq!( e.kind, var(&mut symbols, "x") );
+        assert_eq!( arms.len(), 2 );
+        assert!( matches!( &arms[0].pattern.kind, PatternKind::Constructor { args, .. } if matches!( args[0].kind, PatternKind::Tuple(_) ) ) );
+        assert!( matches!( &arms[0].expr.kind, ExprKind::Block { statements, .. } if statements.len() == 1 ) );
+        assert_eq!( arms[1].expr.kind, ExprKind::Integer(0) );
+    }
+
+    #[test]
+    fn parse_module_should_parse_interpolated_string() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("let x = \"a ${b} c\";", &mut symbols).expect("parse should succeed");
+
+        let e = match &r[0] {
+            Ast::Let { expr, .. } => expr,
+            x => panic!("expected let but found {:?}", x),
+        };
+
+        let span = Span { start : 8, end : 18, line : 1, column : 9 };
+        let string = |s : &str| Expr { kind : ExprKind::String(s.to_string()), span };
+        let b = Expr { kind : var(&mut symbols, "b"), span : Span { start : 13, end : 14, line : 1, column : 14 } };
+
+        assert_eq!( *e, Expr { kind : ExprKind::Interpolated(vec![string("a "), b, string(" c")]), span } );
+    }
+
+    #[test]
+    fn parse_module_should_ignore_doc_comments() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("/// blah\nlet x = 1;\n/** blah */\nlet y = 2;", &mut symbols).expect("parse should succeed");
+
+        assert_eq!( r.len(), 2 );
+    }
+
+    #[test]
+    fn parse_module_should_report_unexpected_token() {
+        let table = vec![ ("let x = ;", Span { start : 8, end : 9, line : 1, column : 9 })
+                        , ("let x = 1; 5", Span { start : 11, end : 12, line : 1, column : 12 })
+                        , ("data x = A;", Span { start : 5, end : 6, line : 1, column : 6 })
+                        , ("let x = \"${1 )}\";", Span { start : 13, end : 14, line : 1, column : 14 })
+                        , ("let x = \"${}\";", Span { start : 8, end : 13, line : 1, column : 9 })
+                        , ("let x = 1", Span { start : 8, end : 9, line : 1, column : 9 })
+                        , ("let x = a == b == c;", Span { start : 15, end : 17, line : 1, column : 16 })
+                        , ("let x = a < b > c;", Span { start : 14, end : 15, line : 1, column : 15 })
+                        , ("infixl 10 +;", Span { start : 7, end : 9, line : 1, column : 8 })
+                        , ("infixl 1 x;", Span { start : 9, end : 10, line : 1, column : 10 })
+                        , ("let x = \\ => 1;", Span { start : 10, end : 12, line : 1, column : 11 })
+                        , ("let x = \\a 1;", Span { start : 11, end : 12, line : 1, column : 12 })
+                        , ("let x = if a { b };", Span { start : 18, end : 19, line : 1, column : 19 })
+                        , ("let x = if a { b } else c;", Span { start : 24, end : 25, line : 1, column : 25 })
+                        , ("data P = P { };", Span { start : 13, end : 14, line : 1, column : 14 })
+                        , ("data P = P { a Int };", Span { start : 15, end : 18, line : 1, column : 16 })
+                        , ("let x = { p with };", Span { start : 17, end : 18, line : 1, column : 18 })
+                        , ("let x = p.A;", Span { start : 10, end : 11, line : 1, column : 11 })
+                        , ("let x = [1 2;", Span { start : 12, end : 13, line : 1, column : 13 })
+                        , ("let x = match y { [..a, b] => 0 };", Span { start : 22, end : 23, line : 1, column : 23 })
+                        , ("pub spec f = a;", Span { start : 4, end : 8, line : 1, column : 5 })
+                        , ("import a.;", Span { start : 9, end : 10, line : 1, column : 10 })
+                        , ("module a b;", Span { start : 9, end : 10, line : 1, column : 10 })
+                        ];
+
+        for (s, expected) in table {
+            let mut symbols = SymbolTable::new();
+
+            let r = parse(s, &mut symbols).map_err(|e| e.span);
+
+            assert_eq!( r, Err(expected), "{}", s );
+        }
+    }
+
+    // Prints an expression with every operator and application fully parenthesized.
+    fn show(e : &Expr, symbols : &SymbolTable) -> String {
+        match &e.kind {
+            ExprKind::Integer(i) => i.to_string(),
+            ExprKind::Var(s) => symbols.resolve(*s).to_string(),
+            ExprKind::Constructor { name, args } if args.is_empty() => symbols.resolve(*name).to_string(),
+            ExprKind::Constructor { name, args } => 
+                format!("{}({})", symbols.resolve(*name), args.iter().map(|a| show(a, symbols)).collect::<Vec<_>>().join(", ")),
+            ExprKind::Call { fun, args } => 
+                format!("{}({})", show(fun, symbols), args.iter().map(|a| show(a, symbols)).collect::<Vec<_>>().join(", ")),
+            ExprKind::Binary { op, lhs, rhs } => 
+                format!("({} {} {})", show(lhs, symbols), symbols.resolve(op.symbol), show(rhs, symbols)),
+            ExprKind::Unary { op, expr } => format!("({}{})", symbols.resolve(op.symbol), show(expr, symbols)),
+            ExprKind::Tuple(es) => format!("({})", es.iter().map(|a| show(a, symbols)).collect::<Vec<_>>().join(", ")),
+            ExprKind::List(es) => format!("[{}]", es.iter().map(|a| show(a, symbols)).collect::<Vec<_>>().join(", ")),
+            ExprKind::Lambda { params, body, .. } => 
+                format!("(\\{} => {})", params.iter().map(|p| symbols.resolve(p.symbol)).collect::<Vec<_>>().join(" "), show(body, symbols)),
+            ExprKind::Block { statements, expr } if statements.is_empty() => format!("{{{}}}", show(expr, symbols)),
+            ExprKind::Record { name, fields } => format!("{}{{{}}}", symbols.resolve(*name), show_fields(fields, symbols)),
+            ExprKind::Field { expr, name } => format!("{}.{}", show(expr, symbols), symbols.resolve(name.symbol)),
+            ExprKind::Update { expr, fields } => format!("{{{} with {}}}", show(expr, symbols), show_fields(fields, symbols)),
+            ExprKind::If { cond, then, otherwise } => 
+                format!("if({}, {}, {})", show(cond, symbols), show(then, symbols), show(otherwise, symbols)),
+            x => panic!("show doesn't handle {:?}", x),
+        }
+    }
+
+    fn show_fields(fields : &[FieldValue], symbols : &SymbolTable) -> String {
+        fields.iter().map(|f| format!("{} = {}", symbols.resolve(f.name.symbol), show(&f.expr, symbols))).collect::<Vec<_>>().join(", ")
+    }
+
+    fn parse_expr(s : &str) -> String {
+        let mut symbols = SymbolTable::new();
+        let r = parse(s, &mut symbols).expect("parse should succeed");
+
+        match r.iter().find(|d| matches!( d, Ast::Let { .. } )) {
+            Some(Ast::Let { expr, .. }) => show(expr, &symbols),
+            x => panic!("expected let but found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn parse_module_should_follow_precedence_table() {
+        let table = vec![ ("let x = 1 + 2 * 3;", "(1 + (2 * 3))")
+                        , ("let x = 1 * 2 + 3;", "((1 * 2) + 3)")
+                        , ("let x = 1 - 2 - 3;", "((1 - 2) - 3)")
+                        , ("let x = a || b || c;", "(a || (b || c))")
+                        , ("let x = a || b && c == d;", "(a || (b && (c == d)))")
+                        , ("let x = a + b < c * d;", "((a + b) < (c * d))")
+                        , ("let x = (1 + 2) * 3;", "((1 + 2) * 3)")
+                        , ("let x = - a * b;", "((-a) * b)")
+                        , ("let x = a - - b;", "(a - (-b))")
+                        , ("let x = - f x;", "(-f(x))")
+                        , ("let x = -1 + 2;", "(-1 + 2)")
+                        , ("let x = f x y + g z;", "(f(x, y) + g(z))")
+                        , ("let x = f(x, y) z;", "f(x, y)(z)")
+                        , ("let x = f (x, y);", "f((x, y))")
+                        , ("let x = f x(y);", "f(x(y))")
+                        , ("let x = Some x;", "Some(x)")
+                        , ("let x = Pair(1, 2);", "Pair(1, 2)")
+                        , ("let x = Pair 1 (f 2);", "Pair(1, f(2))")
+                        , ("let x = Some(1) 2;", "Some(1)(2)")
+                        , ("let x = \\a b => a + b;", "(\\a b => (a + b))")
+                        , ("let x = \\a => \\b => a;", "(\\a => (\\b => a))")
+                        , ("let x = f (\\a => a) 1;", "f((\\a => a), 1)")
+                        , ("let x = 1 + \\a => a * 2;", "(1 + (\\a => (a * 2)))")
+                        , ("let x = (\\a => a) 1;", "(\\a => a)(1)")
+                        , ("let x = add 1;", "add(1)")
+                        , ("let x = !a && b;", "((!a) && b)")
+                        , ("let x = !f x;", "(!f(x))")
+                        , ("let x = a != b;", "(a != b)")
+                        , ("let x = if a { b } else { c };", "if(a, {b}, {c})")
+                        , ("let x = if a { b } else if c { d } else { e };", "if(a, {b}, if(c, {d}, {e}))")
+                        , ("let x = 1 + if a { 2 } else { 3 };", "(1 + if(a, {2}, {3}))")
+                        , ("let x = p.name;", "p.name")
+                        , ("let x = f p.name q;", "f(p.name, q)")
+                        , ("let x = p.a.b + 1;", "(p.a.b + 1)")
+                        , ("let x = f(x).name;", "f(x).name")
+                        , ("let x = -p.age;", "(-p.age)")
+                        , ("let x = P { a = 1, b = f x };", "P{a = 1, b = f(x)}")
+                        , ("let x = g P { a = 1 } 2;", "g(P{a = 1}, 2)")
+                        , ("let x = P { a = 1 }.a;", "P{a = 1}.a")
+                        , ("let x = { p with age = p.age + 1 };", "{p with age = (p.age + 1)}")
+                        , ("let x = { f p with a = 1, b = 2 };", "{f(p) with a = 1, b = 2}")
+                        , ("let x = Mod.P { a = 1 };", "Mod.P{a = 1}")
+                        , ("let x = f Mod.P { a = 1 }.a;", "f(Mod.P{a = 1}.a)")
+                        , ("let x = { Mod.p with a = 1 };", "{Mod.p with a = 1}")
+                        , ("let x = { Mod.P { a = 1 } with a = 2 };", "{Mod.P{a = 1} with a = 2}")
+                        , ("let x = [];", "[]")
+                        , ("let x = [1, f x, [2],];", "[1, f(x), [2]]")
+                        , ("let x = f [1] 2;", "f([1], 2)")
+                        , ("let x = 1 :: 2 :: xs;", "(1 :: (2 :: xs))")
+                        , ("let x = a + 1 :: xs == ys;", "(((a + 1) :: xs) == ys)")
+                        ];
+
+        for (s, expected) in table {
+            assert_eq!( parse_expr(s), expected, "{}", s );
+        }
+    }
+
+    #[test]
+    fn parse_module_should_use_declared_fixity() {
+        let table = vec![ ("infixr 6 -; let x = 1 - 2 - 3;", "(1 - (2 - 3))")
+                        , ("let x = 1 + 2 * 3; infixl 8 +;", "((1 + 2) * 3)")
+                        , ("infix 6 +; let x = 1 * 2 + 3;", "((1 * 2) + 3)")
+                        , ("infixl 4 ==; let x = a == b == c;", "((a == b) == c)")
+                        ];
+
+        for (s, expected) in table {
+            assert_eq!( parse_expr(s), expected, "{}", s );
+        }
+    }
+
+    #[test]
+    fn parse_module_should_report_non_associative_chain() {
+        let table = vec![ ("let x = a == b == c;", Lexeme::DoubleEqual, 15)
+                        , ("let x = 1 < 2 < 3;", Lexeme::LAngle, 14)
+                        , ("let x = f a <= g b != c;", Lexeme::NotEqual, 19)
+                        , ("infix 6 +; let x = a + b + c;", Lexeme::Plus, 25)
+                        ];
+
+        for (s, op, start) in table {
+            let mut symbols = SymbolTable::new();
+
+            let e = parse(s, &mut symbols).expect_err("parse should fail");
+
+            assert_eq!( e.expected, vec![Expected::NonAssociative], "{}", s );
+            assert_eq!( e.found, Some(op), "{}", s );
+            assert_eq!( e.span.start, start, "{}", s );
+        }
+    }
+
+    #[test]
+    fn parse_module_with_recovery_should_ignore_broken_fixity() {
+        let table = vec![ "infixl 9 + x; let v = 1 * 2 + 3;"
+                        , "infixl 10 +; let v = 1 * 2 + 3;"
+                        , "infixl 9 +, let v = 1 * 2 + 3;"
+                        , "infixl 9; let v = 1 * 2 + 3;"
+                        ];
+
+        for s in table {
+            let mut symbols = SymbolTable::new();
+            let tokens = lex(s, &mut symbols).expect("lex should succeed");
+
+            let (decls, errors) = parse_module_with_recovery(tokens, &mut symbols);
+
+            assert_eq!( errors.len(), 1, "{}", s );
+            match decls.last() {
+                Some(Ast::Let { expr, .. }) => assert_eq!( show(expr, &symbols), "((1 * 2) + 3)", "{}", s ),
+                x => panic!("expected let but found {:?}", x),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_module_should_parse_fixity_declaration() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("infixr 5 +;", &mut symbols).expect("parse should succeed");
+
+        assert_eq!( r, vec![ Ast::Fixity { assoc : Assoc::Right
+                                         , precedence : 5
+                                         , op : Ident { symbol : symbols.intern("+"), span : Span { start : 9, end : 10, line : 1, column : 10 } }
+                                         , public : false
+                                         , span : Span { start : 0, end : 11, line : 1, column : 1 }
+                                         } ] );
+    }
+
+    #[test]
+    fn parse_module_should_only_export_user_operator_fixity() {
+        let table = vec![ ("infixl 6 map;", Expected::Operator, 9)
+                        , ("pub infixl 6 +;", Expected::UserOperator, 13)
+                        , ("pub infix 4 ==;", Expected::UserOperator, 12)
+                        ];
+
+        for (s, expected, start) in table {
+            let mut symbols = SymbolTable::new();
+
+            let r = parse(s, &mut symbols);
+
+            assert!( matches!( &r, Err(ParseError { expected : e, span, .. }) if e.contains(&expected) && span.start == start ), "{}: {:?}", s, r );
+        }
+
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("pub infixr 1 <$>;", &mut symbols).expect("parse should succeed");
+
+        assert!( matches!( &r[0], Ast::Fixity { public : true, op, span, .. } 
+                                  if op.symbol == symbols.intern("<$>") && span.start == 0 ) );
+    }
+
+    #[test]
+    fn parse_module_should_parse_user_operators() {
+        let table = vec![ ("let x = a <$> b <$> c;", "((a <$> b) <$> c)")
+                        , ("let x = a <$> b * c;", "((a <$> b) * c)")
+                        , ("let x = f a |> g;", "(f(a) |> g)")
+                        , ("infixr 1 <$>; let x = a <$> b <$> c + d;", "(a <$> (b <$> (c + d)))")
+                        , ("let x = a ++ b :: c; infixr 5 ++;", "(a ++ (b :: c))")
+                        , ("let x = (<$>);", "<$>")
+                        , ("let x = (<$>) f a;", "<$>(f, a)")
+                        , ("let x = a + (<$>);", "(a + <$>)")
+                        ];
+
+        for (s, expected) in table {
+            assert_eq!( parse_expr(s), expected, "{}", s );
+        }
+
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("fun (<$>) f x = f x; let (|>) = 1; spec (<$>) = a; import list ((<$>), map);", &mut symbols).expect("parse should succeed");
+        let op = symbols.intern("<$>");
+
+        assert!( matches!( &r[0], Ast::Fun { name, .. } if name.symbol == op && name.span.start == 4 && name.span.end == 9 ) );
+        assert!( matches!( &r[1], Ast::Let { name, .. } if name.symbol == symbols.intern("|>") ) );
+        assert!( matches!( &r[2], Ast::Spec { name, .. } if name.symbol == op ) );
+        assert!( matches!( &r[3], Ast::Import { names, .. } if names[0].symbol == op && names[1].symbol == symbols.intern("map") ) );
+    }
+
+    #[test]
+    fn parse_module_should_close_nested_type_arguments() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("spec f = List<List<a>>; spec g = List<a>->a; data T<a> = T(Map<a, List<List<a>>>);", &mut symbols).expect("parse should succeed");
+
+        let nested = |t : &Type| matches!( &t.kind, TypeKind::Applied { args, .. } 
+                                           if matches!( &args[0].kind, TypeKind::Applied { args, .. } if args.len() == 1 ) );
+
+        assert!( matches!( &r[0], Ast::Spec { t, .. } if nested(t) ) );
+        assert!( matches!( &r[1], Ast::Spec { t : Type { kind : TypeKind::Arrow { .. }, .. }, .. } ) );
+        assert!( matches!( &r[2], Ast::Data { constructors, .. } 
+                                  if matches!( &constructors[0].fields[0].kind, TypeKind::Applied { args, .. } if nested(&args[1]) ) ) );
+
+        let r = parse("spec f = List<List<a>>>;", &mut symbols);
+
+        assert!( matches!( &r, Err(ParseError { found : Some(Lexeme::RAngle), span, .. }) if span.start == 22 && span.end == 23 ), "{:?}", r );
+    }
+
+    #[test]
+    fn parse_module_should_not_apply_to_match_body() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("let x = match f x { y => y };", &mut symbols).expect("parse should succeed");
+
+        assert!( matches!( &r[0], Ast::Let { expr : Expr { kind : ExprKind::Match { expr, .. }, .. }, .. } 
+                           if matches!( expr.kind, ExprKind::Call { .. } ) ) );
+    }
+
+    fn show_pattern(p : &Pattern, symbols : &SymbolTable) -> String {
+        let all = |ps : &Vec<Pattern>, sep| ps.iter().map(|p| show_pattern(p, symbols)).collect::<Vec<_>>().join(sep);
+
+        match &p.kind {
+            PatternKind::Wildcard => "_".to_string(),
+            PatternKind::Var(s) => symbols.resolve(*s).to_string(),
+            PatternKind::Bool(b) => b.to_string(),
+            PatternKind::Integer(i) => i.to_string(),
+            PatternKind::Decimal(d) => d.to_string(),
+            PatternKind::String(s) => format!("{:?}", s),
+            PatternKind::Char(c) => format!("{:?}", c),
+            PatternKind::Tuple(ps) => format!("({})", all(ps, ", ")),
+            PatternKind::Constructor { name, args } if args.is_empty() => symbols.resolve(*name).to_string(),
+            PatternKind::Constructor { name, args } => format!("{}({})", symbols.resolve(*name), all(args, ", ")),
+            PatternKind::Or(ps) => format!("[{}]", all(ps, " | ")),
+            PatternKind::As { name, pattern } => format!("{}@{}", symbols.resolve(name.symbol), show_pattern(pattern, symbols)),
+            PatternKind::Record { name, fields } => {
+                let fields = fields.iter().map(|f| format!("{} = {}", symbols.resolve(f.name.symbol), show_pattern(&f.pattern, symbols)));
+                format!("{}{{{}}}", symbols.resolve(*name), fields.collect::<Vec<_>>().join(", "))
+            },
+            PatternKind::List { items, rest : None } => format!("[{}]", all(items, ", ")),
+            PatternKind::List { items, rest : Some(rest) } => format!("[{}; ..{}]", all(items, ", "), show_pattern(rest, symbols)),
+        }
+    }
+
+    fn parse_arms(s : &str, symbols : &mut SymbolTable) -> Vec<MatchArm> {
+        let r = parse(s, symbols).expect("parse should succeed");
+
+        match &r[0] {
+            Ast::Let { expr : Expr { kind : ExprKind::Match { arms, .. }, .. }, .. } => arms.clone(),
+            x => panic!("expected match but found {:?}", x),
+        }
+    }
+
+    #[test]
+    fn parse_module_should_parse_patterns() {
+        let table = vec![ ("_", "_")
+                        , ("x", "x")
+                        , ("true", "true")
+                        , ("-5", "-5")
+                        , ("1.5", "1.5")
+                        , ("\"s\"", "\"s\"")
+                        , ("'c'", "'c'")
+                        , ("(a, _, 1)", "(a, _, 1)")
+                        , ("(a)", "a")
+                        , ("None", "None")
+                        , ("Some x", "Some(x)")
+                        , ("Some(x)", "Some(x)")
+                        , ("Pair(a, b)", "Pair(a, b)")
+                        , ("Pair (a, b)", "Pair((a, b))")
+                        , ("Cons x (Cons y _)", "Cons(x, Cons(y, _))")
+                        , ("Pair None x", "Pair(None, x)")
+                        , ("1 | 2 | 3", "[1 | 2 | 3]")
+                        , ("Some(1 | 2)", "Some([1 | 2])")
+                        , ("x @ Some _", "x@Some(_)")
+                        , ("x @ (1 | 2)", "x@[1 | 2]")
+                        , ("x @ 1 | y @ 2", "[x@1 | y@2]")
+                        , ("Some(x @ Pair(_, b))", "Some(x@Pair(_, b))")
+                        , ("Person { name = n, age = 3 }", "Person{name = n, age = 3}")
+                        , ("Person { name }", "Person{name = name}")
+                        , ("Some(P { a = A | B })", "Some(P{a = [A | B]})")
+                        , ("p @ P { a = Some x }", "p@P{a = Some(x)}")
+                        , ("[]", "[]")
+                        , ("[a, _]", "[a, _]")
+                        , ("[a, ..rest]", "[a; ..rest]")
+                        , ("[..]", "[; .._]")
+                        , ("[Some x, ..xs]", "[Some(x); ..xs]")
+                        , ("List.Cons x List.Nil", "List.Cons(x, List.Nil)")
+                        , ("Opt.Some(x)", "Opt.Some(x)")
+                        , ("Cons [a] []", "Cons([a], [])")
+                        ];
+
+        for (p, expected) in table {
+            let mut symbols = SymbolTable::new();
+            let s = format!("let x = match y {{ {} => 0 }};", p);
+
+            let arms = parse_arms(&s, &mut symbols);
+
+            assert_eq!( show_pattern(&arms[0].pattern, &symbols), expected, "{}", p );
+        }
+    }
+
+    #[test]
+    fn parse_module_should_parse_guards() {
+        let mut symbols = SymbolTable::new();
+
+        let arms = parse_arms("let x = match y { Some x if x > 5 => x, Some _ | None => 0 };", &mut symbols);
+
+        assert_eq!( arms.len(), 2 );
+        assert!( matches!( &arms[0].guard, Some(Expr { kind : ExprKind::Binary { .. }, .. }) ) );
+        assert_eq!( arms[0].span, Span { start : 18, end : 38, line : 1, column : 19 } );
+        assert_eq!( arms[1].guard, None );
+    }
+
+    #[test]
+    fn parse_module_should_report_bad_patterns() {
+        let table = vec![ ("let x = match y { x @ => 0 };", Span { start : 22, end : 24, line : 1, column : 23 })
+                        , ("let x = match y { 1 | => 0 };", Span { start : 22, end : 24, line : 1, column : 23 })
+                        , ("let x = match y { a + b => 0 };", Span { start : 20, end : 21, line : 1, column : 21 })
+                        , ("let x = match y { a if => 0 };", Span { start : 23, end : 25, line : 1, column : 24 })
+                        ];
+
+        for (s, expected) in table {
+            let mut symbols = SymbolTable::new();
+
+            let r = parse(s, &mut symbols).map_err(|e| e.span);
+
+            assert_eq!( r, Err(expected), "{}", s );
+        }
+    }
+
+    #[test]
+    fn parse_module_should_report_expected_and_found() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("data X = A | B 5", &mut symbols);
+
+        assert_eq!( r, Err(ParseError { expected : vec![ Expected::Lexeme(Lexeme::LCurl)
+                                                      , Expected::Lexeme(Lexeme::LParen)
+                                                      , Expected::Lexeme(Lexeme::OrBar)
+                                                      , Expected::Lexeme(Lexeme::SemiColon)
+                                                      ]
+                                      , found : Some(Lexeme::Integer(5))
+                                      , span : Span { start : 15, end : 16, line : 1, column : 16 }
+                                      }) );
+    }
+
+    #[test]
+    fn parse_module_should_report_end_of_input() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("fun f x =", &mut symbols);
+
+        assert_eq!( r, Err(ParseError { expected : vec![Expected::Expr]
+                                      , found : None
+                                      , span : Span { start : 8, end : 9, line : 1, column : 9 }
+                                      }) );
+    }
+
+    #[test]
+    fn parse_module_should_report_expected_declaration() {
+        let mut symbols = SymbolTable::new();
+
+        let r = parse("5", &mut symbols);
+
+        assert!( matches!( r, Err(ParseError { expected, found : Some(Lexeme::Integer(5)), .. }) if expected == vec![Expected::Declaration] ) );
+    }
+
+    #[test]
+    fn parse_module_with_recovery_should_report_all_errors() {
+        let mut symbols = SymbolTable::new();
+        let s = "let a = ;\nfun f x = { let y = ; y };\nlet b = 1;\n) ) let c = 2;\ndata D = E | ;\nlet d = let e = 3;\nspec s = Int;";
+        let tokens = lex(s, &mut symbols).expect("lex should succeed");
+
+        let (decls, errors) = parse_module_with_recovery(tokens, &mut symbols);
+
+        let spans = errors.iter().map(|e| (e.span.line, e.span.column)).collect::<Vec<_>>();
+
+        assert_eq!( spans, vec![(1, 9), (2, 21), (4, 1), (5, 14), (6, 9)] );
+
+        let names = decls.iter().map(|d| match d {
+            Ast::Let { name, .. } | Ast::Spec { name, .. } | Ast::Fun { name, .. } => symbols.resolve(name.symbol).to_string(),
+            x => panic!("unexpected declaration {:?}", x),
+        }).collect::<Vec<_>>();
+
+        // The broken statement in f is skipped on its own, so f is kept.
+        assert_eq!( names, vec!["f", "b", "c", "e", "s"] );
+    }
+
+    #[test]
+    fn parse_module_with_recovery_should_report_errors_within_declaration() {
+        let mut symbols = SymbolTable::new();
+        let s = "fun f x = {\n  let a = ;\n  let b = (1, ];\n  let c = match x { 1 => , (2, y) => y, _ => ) };\n  c\n};\nlet d = 1;";
+        let tokens = lex(s, &mut symbols).expect("lex should succeed");
+
+        let (decls, errors) = parse_module_with_recovery(tokens, &mut symbols);
+
+        let spans = errors.iter().map(|e| (e.span.line, e.span.column)).collect::<Vec<_>>();
+
+        assert_eq!( spans, vec![(2, 11), (3, 15), (4, 26), (4, 46)] );
+        assert_eq!( decls.len(), 2 );
+
+        let statements = match &decls[0] {
+            Ast::Fun { body : Expr { kind : ExprKind::Block { statements, expr }, .. }, .. } => {
+                assert_eq!( show(expr, &symbols), "c" );
+                statements
+            },
+            x => panic!("expected fun with block but found {:?}", x),
+        };
+
+        assert_eq!( statements.len(), 1 );
+        match &statements[0] {
+            Ast::Let { name, expr : Expr { kind : ExprKind::Match { arms, .. }, .. }, .. } => {
+                assert_eq!( symbols.resolve(name.symbol), "c" );
+                assert_eq!( arms.len(), 1 );
+                assert_eq!( show(&arms[0].expr, &symbols), "y" );
+            },
+            x => panic!("expected let of a match but found {:?}", x),
+        }
+    }
+}
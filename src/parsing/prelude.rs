@@ -0,0 +1,62 @@
+
+use super::ast::Ast;
+use super::lex::lex;
+use super::parser::parse_module;
+use super::symbol::SymbolTable;
+
+pub const PRELUDE : &str = include_str!("prelude.si");
+
+/// The loader adds the prelude as this module and every module imports it.  User 
+/// modules can't be called this.
+pub const NAME : &str = "prelude";
+
+// The qualified names that list syntax desugars to.  A module's own Cons or Nil can't 
+// take their place.
+pub const CONS : &str = "Prelude.Cons";
+pub const NIL : &str = "Prelude.Nil";
+
+// Interpolated strings desugar to calls to this.
+pub const CONCAT : &str = "Prelude.concat";
+
+/// Parses the declarations that every module can use without importing them.
+pub fn prelude(symbols : &mut SymbolTable) -> Vec<Ast> {
+    let tokens = lex(PRELUDE, symbols).expect("prelude should lex");
+    parse_module(tokens, symbols).expect("prelude should parse")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::ast::TypeKind;
+
+    #[test]
+    fn prelude_should_define_list() {
+        let mut symbols = SymbolTable::new();
+
+        let decls = prelude(&mut symbols);
+
+        let constructors = decls.iter().find_map(|d| match d {
+            Ast::Data { name, constructors, .. } if name.symbol == symbols.intern("List") => Some(constructors),
+            _ => None,
+        }).expect("prelude should define List");
+
+        assert!( decls.iter().all(|d| matches!( d, Ast::Data { public : true, .. } | Ast::Fun { public : true, .. } )) );
+
+        assert_eq!( constructors.len(), 2 );
+        assert_eq!( constructors[0].name.symbol, symbols.intern("Nil") );
+        assert_eq!( constructors[1].name.symbol, symbols.intern("Cons") );
+        assert_eq!( constructors[1].fields[0].kind, TypeKind::Generic(symbols.intern("a")) );
+        assert!( matches!( constructors[1].fields[1].kind, TypeKind::Applied { name, .. } if name == symbols.intern("List") ) );
+    }
+
+    #[test]
+    fn prelude_should_define_concat() {
+        let mut symbols = SymbolTable::new();
+
+        let decls = prelude(&mut symbols);
+
+        let concat = symbols.intern("concat");
+        assert!( decls.iter().any(|d| matches!( d, Ast::Fun { name, params, public : true, .. } if name.symbol == concat && params.len() == 2 )) );
+        assert_eq!( CONCAT, format!("Prelude.{}", symbols.resolve(concat)) );
+    }
+}
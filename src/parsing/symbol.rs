@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct Symbol(u32);
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    ids : HashMap<String, Symbol>,
+    names : Vec<String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable { ids : HashMap::new(), names : vec![] }
+    }
+
+    pub fn intern(&mut self, name : &str) -> Symbol {
+        if let Some(symbol) = self.ids.get(name) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        self.ids.insert(name.to_string(), symbol);
+        self.names.push(name.to_string());
+        symbol
+    }
+
+    pub fn get(&self, name : &str) -> Option<Symbol> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn resolve(&self, symbol : Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn intern_should_reuse_symbol_for_same_name() {
+        let mut symbols = SymbolTable::new();
+
+        let a = symbols.intern("blah");
+        let b = symbols.intern("other");
+        let c = symbols.intern("blah");
+
+        assert_eq!( a, c );
+        assert_ne!( a, b );
+        assert_eq!( symbols.resolve(a), "blah" );
+        assert_eq!( symbols.resolve(b), "other" );
+    }
+
+    #[test]
+    fn get_should_not_intern() {
+        let mut symbols = SymbolTable::new();
+
+        assert_eq!( symbols.get("blah"), None );
+
+        let a = symbols.intern("blah");
+
+        assert_eq!( symbols.get("blah"), Some(a) );
+    }
+}